@@ -3,39 +3,135 @@
 //! This module provides the core types and functions for finding and parsing
 //! .meta configuration files (JSON and YAML formats).
 
-use anyhow::Context;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
+mod defaults;
+mod diagnostics;
+mod discover;
+mod document;
+mod git;
+mod graph;
+mod ignore;
+mod include;
+mod interpolate;
+mod overlay;
+mod profile;
+mod project_id;
+mod project_path;
+mod query;
+mod rewrite;
+mod user;
+mod validate;
+
+pub use defaults::{DefaultsOrigin, PartialDefaults, ResolvedDefaults};
+pub use discover::{find_untracked_repos, UntrackedRepo};
+pub use document::MetaConfigDocument;
+pub use graph::{DependencyGraph, ExecutionLevel, ExecutionPlan, UnresolvedDependency};
+pub use ignore::IgnoreMatcher;
+pub use overlay::{overlay_path, Overlay, OverlayReport, OverlayValue, ProjectOverlay};
+pub use profile::{Profile, ProfileSelection};
+pub use project_id::{IndexedProject, ProjectId, ProjectIndex};
+pub use project_path::{
+    path_conflicts, PathConflict, PathConflictKind, ProjectPath, ProjectPathError,
+};
+pub use query::ProjectQuery;
+pub use rewrite::resolve_repo_url;
+pub use user::{ConfigSources, UserConfig};
+pub use validate::{validate, Diagnostic, DiagnosticKind};
+
 /// Represents a project entry in the .meta config.
 /// Can be either a simple git URL string or an extended object with additional fields.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
+#[schemars(deny_unknown_fields)]
 pub enum ProjectEntry {
     /// Simple format: just a git URL string
     Simple(String),
     /// Extended format: object with repo, path, tags, and dependency info
     Extended {
         /// Git remote URL (optional for local-only projects).
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         repo: Option<String>,
-        #[serde(default)]
+        /// Directory relative to the meta root (defaults to the project name).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         path: Option<String>,
-        #[serde(default)]
+        /// Tags for selecting groups of projects.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         tags: Vec<String>,
-        #[serde(default)]
+        /// What this project provides (e.g., APIs, libraries)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         provides: Vec<String>,
-        #[serde(default)]
+        /// What this project depends on (other project names or provided items)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
         depends_on: Vec<String>,
         /// If true, this directory contains a nested .meta config
-        #[serde(default)]
+        #[serde(default, skip_serializing_if = "is_false")]
         meta: bool,
     },
 }
 
+impl ProjectEntry {
+    /// Build the entry that represents `info` in a .meta file.
+    ///
+    /// Uses the simple string form when the project only has a repo URL and
+    /// lives at the default path (its name); otherwise uses the extended form.
+    pub fn from_info(info: &ProjectInfo) -> Self {
+        ProjectEntry::Extended {
+            repo: info.repo.clone(),
+            path: Some(info.path.clone()),
+            tags: info.tags.clone(),
+            provides: info.provides.clone(),
+            depends_on: info.depends_on.clone(),
+            meta: info.meta,
+        }
+        .compact(&info.name)
+    }
+
+    /// Return the most compact equivalent form of this entry for project `name`.
+    ///
+    /// An extended entry with only a repo URL (and a path equal to `name`)
+    /// collapses to the simple form. A `path` equal to `name` is dropped.
+    pub fn compact(self, name: &str) -> Self {
+        match self {
+            ProjectEntry::Simple(url) => ProjectEntry::Simple(url),
+            ProjectEntry::Extended {
+                repo,
+                path,
+                tags,
+                provides,
+                depends_on,
+                meta,
+            } => {
+                let path = path.filter(|p| p != name);
+                match repo {
+                    Some(url)
+                        if path.is_none()
+                            && tags.is_empty()
+                            && provides.is_empty()
+                            && depends_on.is_empty()
+                            && !meta =>
+                    {
+                        ProjectEntry::Simple(url)
+                    }
+                    repo => ProjectEntry::Extended {
+                        repo,
+                        path,
+                        tags,
+                        provides,
+                        depends_on,
+                        meta,
+                    },
+                }
+            }
+        }
+    }
+}
+
 /// Parsed project info after normalization
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProjectInfo {
     pub name: String,
     pub path: String,
@@ -52,6 +148,9 @@ pub struct ProjectInfo {
     /// If true, this directory contains a nested .meta config
     #[serde(default)]
     pub meta: bool,
+    /// The .meta file (or included fragment) this project is defined in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
 }
 
 impl ProjectInfo {
@@ -59,45 +158,198 @@ impl ProjectInfo {
     pub fn has_no_repo(&self) -> bool {
         self.repo.is_none()
     }
+
+    /// Describe an existing checkout at `path` (relative to `meta_dir`).
+    ///
+    /// The name is the last path component and the repo URL is read from the
+    /// checkout's git config (`origin`, else the first remote), following a
+    /// `.git` file's `gitdir:` for submodules and worktrees. No `git` binary
+    /// or network access is needed. A checkout without remotes gets no repo.
+    ///
+    /// Use `ProjectEntry::from_info` to get the entry to write to .meta.
+    pub fn from_git_checkout(meta_dir: &Path, path: &str) -> crate::Result<ProjectInfo> {
+        let path = relative_path_str(Path::new(&path.replace('\\', "/")));
+        let dir = meta_dir.join(&path);
+        if !git::is_repo(&dir) {
+            return Err(crate::Error::NotGitRepo { path: dir });
+        }
+        let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        Ok(ProjectInfo {
+            name,
+            repo: git::remote_url(&dir),
+            path,
+            ..Default::default()
+        })
+    }
+
+    /// Describe each submodule declared in `<meta_dir>/.gitmodules`.
+    ///
+    /// Relative submodule URLs (`../lib.git`) are resolved against the remote
+    /// of the checkout at `meta_dir`, as git does. Projects are named after
+    /// the last component of their path, or the full path if that name is
+    /// already taken. The submodules don't need to be checked out.
+    pub fn from_gitmodules(meta_dir: &Path) -> crate::Result<Vec<ProjectInfo>> {
+        let gitmodules = meta_dir.join(".gitmodules");
+        let content =
+            std::fs::read_to_string(&gitmodules).map_err(|e| crate::Error::io(&gitmodules, e))?;
+        let superproject = git::remote_url(meta_dir);
+
+        let mut projects: Vec<ProjectInfo> = Vec::new();
+        for submodule in git::parse_gitmodules(&content) {
+            let basename = submodule.path.rsplit('/').next().unwrap_or(&submodule.path);
+            let name = if projects.iter().any(|p| p.name == basename) {
+                submodule.path.clone()
+            } else {
+                basename.to_string()
+            };
+            projects.push(ProjectInfo {
+                name,
+                repo: submodule
+                    .url
+                    .map(|url| git::resolve_submodule_url(&url, superproject.as_deref())),
+                path: submodule.path,
+                ..Default::default()
+            });
+        }
+        Ok(projects)
+    }
+
+    /// Normalize the .meta entry for project `name`.
+    pub(crate) fn from_entry(
+        name: String,
+        entry: ProjectEntry,
+        source: Option<PathBuf>,
+    ) -> ProjectInfo {
+        let (repo, path, tags, provides, depends_on, meta) = match entry {
+            // Simple string -> git URL
+            ProjectEntry::Simple(url) => (Some(url), name.clone(), vec![], vec![], vec![], false),
+            // Extended object -> repo with additional fields
+            // meta: true indicates this project is also a meta-repo (has its own .meta)
+            ProjectEntry::Extended {
+                repo,
+                path,
+                tags,
+                provides,
+                depends_on,
+                meta,
+            } => {
+                let resolved_path = path.unwrap_or_else(|| name.clone());
+                (repo, resolved_path, tags, provides, depends_on, meta)
+            }
+        };
+        ProjectInfo {
+            name,
+            path: path.replace('\\', "/"),
+            repo,
+            tags,
+            provides,
+            depends_on,
+            meta,
+            source,
+        }
+    }
 }
 
 /// Default settings that can be configured in .meta
-#[derive(Debug, Deserialize, Clone)]
+///
+/// Also settable in the user config, an overlay and `META_*` environment
+/// variables; see `resolve_meta_defaults` for the precedence.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct MetaDefaults {
     /// Run commands in parallel by default (defaults to true)
     #[serde(default = "default_true")]
     pub parallel: bool,
+    /// Maximum number of projects to run at once when parallel (unlimited if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Project query selecting the projects commands run on by default
+    /// (e.g. `!legacy`; see `ProjectQuery`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Shell used to run commands (the platform default if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Per-project command timeout in seconds (no timeout if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Stop starting new projects after the first failure (defaults to false)
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub fail_fast: bool,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_default_defaults(defaults: &MetaDefaults) -> bool {
+    *defaults == MetaDefaults::default()
+}
+
+/// Serialize a `HashMap` with its keys sorted, so written files are deterministic.
+fn serialize_sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 impl Default for MetaDefaults {
     fn default() -> Self {
-        Self { parallel: true }
+        Self {
+            parallel: true,
+            max_concurrency: None,
+            filter: None,
+            shell: None,
+            timeout_secs: None,
+            fail_fast: false,
+        }
     }
 }
 
 /// The meta configuration file structure
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct MetaConfig {
     /// Projects in this meta repo. Values can be:
     /// - A git URL string: `"project": "git@github.com:org/repo.git"`
     /// - An extended object: `"project": { "repo": "...", "path": "...", "meta": true }`
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub projects: HashMap<String, ProjectEntry>,
-    #[serde(default)]
+    /// Directory names or glob patterns to ignore, relative to this file (see `IgnoreMatcher`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub ignore: Vec<String>,
-    #[serde(default)]
+    /// Default settings for commands run across projects.
+    #[serde(default, skip_serializing_if = "is_default_defaults")]
     pub defaults: MetaDefaults,
     /// Custom directory for worktrees (overrides default .worktrees/)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub worktrees_dir: Option<String>,
+    /// Fragment files to pull projects, ignore patterns and defaults from,
+    /// relative to this file. Local entries override included ones.
+    #[serde(default, alias = "extends", skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Repo URL prefix rewrites (`"git@github.com:": "https://github.com/"`),
+    /// applied on top of the user config's rules.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub url_rewrites: BTreeMap<String, String>,
+    /// Expand GitHub shorthand repos (`org/repo`) to HTTPS URLs. Off by
+    /// default, so `org/repo` can be a relative path.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub github_shorthand: bool,
+    /// Named profiles (e.g. `ci`, `minimal`) that narrow the projects and
+    /// override defaults and URL rewrites; see `ParseOptions::profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
 }
 
 /// Determines the format of a config file based on extension
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigFormat {
     Json,
     Yaml,
@@ -116,7 +368,7 @@ pub fn find_meta_config_in(dir: &Path) -> Option<(PathBuf, ConfigFormat)> {
     ] {
         let candidate = dir.join(name);
         if candidate.exists() && candidate.is_file() {
-            return Some((candidate, format.clone()));
+            return Some((candidate, *format));
         }
     }
     None
@@ -153,7 +405,7 @@ pub fn find_meta_config(
         for (name, format) in &candidates {
             let candidate = current_dir.join(name);
             if candidate.exists() && candidate.is_file() {
-                return Some((candidate, format.clone()));
+                return Some((candidate, *format));
             }
         }
         if let Some(parent) = current_dir.parent() {
@@ -164,57 +416,259 @@ pub fn find_meta_config(
     }
 }
 
-/// Parse a meta config file (JSON or YAML) and return normalized project info and ignore list.
-pub fn parse_meta_config(meta_path: &Path) -> anyhow::Result<(Vec<ProjectInfo>, Vec<String>)> {
-    let config_str = std::fs::read_to_string(meta_path)
-        .with_context(|| format!("Failed to read meta config file: '{}'", meta_path.display()))?;
-
-    // Determine format from file extension
-    let path_str = meta_path.to_string_lossy();
-    let config: MetaConfig = if path_str.ends_with(".yaml") || path_str.ends_with(".yml") {
-        serde_yaml_ng::from_str(&config_str)
-            .with_context(|| format!("Failed to parse YAML config file: {}", meta_path.display()))?
-    } else {
-        serde_json::from_str(&config_str)
-            .with_context(|| format!("Failed to parse JSON config file: {}", meta_path.display()))?
+impl ConfigFormat {
+    /// Detect the format from a file name: `.yaml`/`.yml` is YAML, anything else JSON.
+    pub fn from_path(path: &Path) -> Self {
+        let path_str = path.to_string_lossy();
+        if path_str.ends_with(".yaml") || path_str.ends_with(".yml") {
+            ConfigFormat::Yaml
+        } else {
+            ConfigFormat::Json
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Read a meta config file (JSON or YAML) into its raw `MetaConfig` form.
+///
+/// Unlike `parse_meta_config`, entries are not normalized and `include`d
+/// fragments are not merged in, so the result can be modified and written
+/// back with `write_meta_config`.
+pub fn read_meta_config(meta_path: &Path) -> crate::Result<MetaConfig> {
+    load_source(meta_path).map(|(_, _, config)| config)
+}
+
+/// Read and parse a meta config file, keeping its source text for diagnostics.
+fn load_source(meta_path: &Path) -> crate::Result<(String, ConfigFormat, MetaConfig)> {
+    let config_str =
+        std::fs::read_to_string(meta_path).map_err(|e| crate::Error::io(meta_path, e))?;
+
+    let format = ConfigFormat::from_path(meta_path);
+    let config = match format {
+        ConfigFormat::Yaml => serde_yaml_ng::from_str(&config_str).map_err(|e| {
+            let location = e.location();
+            diagnostics::parse_error(
+                meta_path,
+                format,
+                &config_str,
+                location.as_ref().map(|l| l.line()),
+                location.as_ref().map(|l| l.column()),
+                &e.to_string(),
+            )
+        })?,
+        ConfigFormat::Json => serde_json::from_str(&config_str).map_err(|e| {
+            diagnostics::parse_error(
+                meta_path,
+                format,
+                &config_str,
+                Some(e.line()),
+                Some(e.column()),
+                &e.to_string(),
+            )
+        })?,
     };
+    Ok((config_str, format, config))
+}
+
+/// Load the effective config for a meta config file.
+///
+/// `include`d fragments are merged in, the user's overlay for this meta root
+/// is applied (see `overlay_path`), `${VAR}` references in `repo`, `path`
+/// and `worktrees_dir` are expanded from the environment, and repo URLs are
+/// resolved with `url_rewrites` from the user config and the file, expanding
+/// GitHub shorthand if the file sets `github_shorthand` (see
+/// `resolve_repo_url`). Fails with `Error::ConfigInterpolation` if a
+/// referenced variable is unset.
+///
+/// The profile named by `META_PROFILE`, if any, is applied; use
+/// `load_meta_config_with` to select one explicitly.
+pub fn load_meta_config(meta_path: &Path) -> crate::Result<MetaConfig> {
+    load_meta_config_with(meta_path, &ParseOptions::default())
+}
+
+/// Like `load_meta_config`, with explicit `ParseOptions`.
+///
+/// Fails with `Error::UnknownProfile` if the file defines profiles but not
+/// the selected one.
+pub fn load_meta_config_with(
+    meta_path: &Path,
+    options: &ParseOptions,
+) -> crate::Result<MetaConfig> {
+    resolve_meta_config(meta_path, options).map(|resolved| resolved.config)
+}
+
+/// Report which values of the effective config came from the user's overlay.
+///
+/// Returns `None` if there is no overlay file for this meta root.
+pub fn overlay_report(meta_path: &Path) -> crate::Result<Option<OverlayReport>> {
+    overlay_report_with(meta_path, &ParseOptions::default())
+}
+
+/// Like `overlay_report`, with explicit `ParseOptions`.
+pub fn overlay_report_with(
+    meta_path: &Path,
+    options: &ParseOptions,
+) -> crate::Result<Option<OverlayReport>> {
+    resolve_meta_config(meta_path, options).map(|resolved| resolved.overlay)
+}
+
+fn resolve_meta_config(
+    meta_path: &Path,
+    options: &ParseOptions,
+) -> crate::Result<include::Resolved> {
+    let mut resolved = include::resolve(meta_path)?;
+
+    let profile = profile::select(&resolved, &options.profile, &options.sources)?;
+    if let Some((name, profile)) = &profile {
+        profile::apply_settings(&mut resolved, name, profile);
+    }
+
+    let meta_root = meta_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let overlay_file = overlay::overlay_path_in(&options.sources.meta_dir(), meta_root);
+    if let Some(overlay) = overlay::load(&overlay_file)? {
+        resolved.overlay = Some(overlay::apply(&mut resolved, overlay, &overlay_file));
+    }
+
+    interpolate::apply(&mut resolved, |name| options.sources.var(name))?;
+    let (user, user_path) = options.sources.user_config(options.strict)?;
+    rewrite::apply(&mut resolved.config, &user.url_rewrites);
+    resolved.resolve_defaults(
+        defaults::settings(&user.defaults, DefaultsOrigin::User(user_path)),
+        defaults::env_settings(|name| options.sources.var(name))?,
+    );
+
+    // Before the profile's filter, so `depends_on` entries naming filtered
+    // out projects still resolve
+    if options.strict {
+        for file in &resolved.files {
+            let diagnostics = validate::validate_source(
+                &file.source,
+                file.format,
+                &file.config,
+                &resolved.config,
+            );
+            if !diagnostics.is_empty() {
+                return Err(crate::Error::ConfigInvalid {
+                    path: file.path.clone(),
+                    diagnostics,
+                });
+            }
+        }
+    }
+    if let Some((_, profile)) = &profile {
+        profile::apply_filter(&mut resolved, profile)?;
+    }
+    Ok(resolved)
+}
+
+/// Resolve the defaults for the meta repo in `start_dir` from every layer,
+/// with the origin of each setting (for e.g. `meta config show --origin`).
+///
+/// Layers, lowest precedence first: built-in defaults, the user config
+/// (`~/.meta/config.yaml`), the .meta file in `start_dir` with its includes,
+/// the user's overlay for it, and `META_*` environment variables. Without a
+/// .meta file in `start_dir`, only the built-in, user and environment layers
+/// apply.
+pub fn resolve_meta_defaults(start_dir: &Path) -> crate::Result<ResolvedDefaults> {
+    resolve_meta_defaults_with(start_dir, &ParseOptions::default())
+}
+
+/// Like `resolve_meta_defaults`, with explicit `ParseOptions`.
+pub fn resolve_meta_defaults_with(
+    start_dir: &Path,
+    options: &ParseOptions,
+) -> crate::Result<ResolvedDefaults> {
+    if let Some((config_path, _format)) = find_meta_config_in(start_dir) {
+        return resolve_meta_config(&config_path, options).map(|resolved| resolved.defaults);
+    }
+    let (user, user_path) = options.sources.user_config(options.strict)?;
+    Ok(defaults::resolve(
+        defaults::settings(&user.defaults, DefaultsOrigin::User(user_path))
+            .into_iter()
+            .chain(defaults::env_settings(|name| options.sources.var(name))?),
+    ))
+}
+
+/// Options for `parse_meta_config_with`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Fail with `Error::ConfigInvalid` if `validate` reports any diagnostics
+    /// (unknown keys, escaping paths, unresolved dependencies, ...).
+    /// Also fails if the user config doesn't load.
+    pub strict: bool,
+    /// The profile to apply (see `Profile`); `META_PROFILE` by default.
+    pub profile: ProfileSelection,
+    /// Where the user config, overlays and environment variables are read
+    /// from (the real meta data directory and environment by default). A
+    /// user config file that fails to load is skipped, or fails the parse
+    /// if `strict`; `UserConfig::load` reports its error.
+    pub sources: ConfigSources,
+}
+
+/// Parse a meta config file (JSON or YAML) and return normalized project info and ignore list.
+///
+/// Projects come from the effective config (see `load_meta_config`). Their
+/// paths are normalized (see `ProjectPath`). Paths outside the meta root,
+/// like `../shared-lib` or absolute paths, are kept, as are paths referring
+/// to the meta root itself (`.`), which aren't normalized; `validate` reports
+/// both and strict parsing rejects them (see `DiagnosticKind::PathEscapesRoot`).
+///
+/// Fails with `Error::Io` if the file can't be read, or `Error::ConfigParse`
+/// (with the line, column and a source snippet, when known) if it can't be
+/// parsed.
+pub fn parse_meta_config(meta_path: &Path) -> crate::Result<(Vec<ProjectInfo>, Vec<String>)> {
+    parse_meta_config_with(meta_path, &ParseOptions::default())
+}
+
+/// Like `parse_meta_config`, with explicit `ParseOptions`.
+pub fn parse_meta_config_with(
+    meta_path: &Path,
+    options: &ParseOptions,
+) -> crate::Result<(Vec<ProjectInfo>, Vec<String>)> {
+    let include::Resolved {
+        config,
+        sources,
+        files,
+        ..
+    } = resolve_meta_config(meta_path, options)?;
 
     // Convert project entries to normalized ProjectInfo
     let mut projects: Vec<ProjectInfo> = config
         .projects
         .into_iter()
         .map(|(name, entry)| {
-            let (repo, path, tags, provides, depends_on, meta) = match entry {
-                // Simple string -> git URL
-                ProjectEntry::Simple(url) => {
-                    (Some(url), name.clone(), vec![], vec![], vec![], false)
-                }
-                // Extended object -> repo with additional fields
-                // meta: true indicates this project is also a meta-repo (has its own .meta)
-                ProjectEntry::Extended {
-                    repo,
-                    path,
-                    tags,
-                    provides,
-                    depends_on,
-                    meta,
-                } => {
-                    let resolved_path = path.unwrap_or_else(|| name.clone());
-                    (repo, resolved_path, tags, provides, depends_on, meta)
+            let source = sources.get(&name).cloned();
+            let mut project = ProjectInfo::from_entry(name, entry, source);
+            match ProjectPath::allowing_escape(&project.path) {
+                Ok(path) => project.path = path.to_string(),
+                Err(source) if options.strict => {
+                    return Err(crate::Error::InvalidProjectPath {
+                        path: project
+                            .source
+                            .clone()
+                            .unwrap_or_else(|| files[0].path.clone()),
+                        project: project.name.clone(),
+                        value: project.path.clone(),
+                        source,
+                    });
                 }
-            };
-            let path = path.replace('\\', "/");
-            ProjectInfo {
-                name,
-                path,
-                repo,
-                tags,
-                provides,
-                depends_on,
-                meta,
+                // Kept as written; `validate` reports it
+                Err(_) => {}
             }
+            Ok(project)
         })
-        .collect();
+        .collect::<crate::Result<_>>()?;
 
     // Sort projects alphabetically by name for deterministic order
     projects.sort_by(|a, b| a.name.cmp(&b.name));
@@ -222,26 +676,78 @@ pub fn parse_meta_config(meta_path: &Path) -> anyhow::Result<(Vec<ProjectInfo>,
     Ok((projects, config.ignore))
 }
 
-/// Load defaults from a meta config file.
-/// Returns MetaDefaults::default() if no config found or on parse error.
-pub fn load_meta_defaults(start_dir: &Path) -> MetaDefaults {
-    let Some((config_path, _format)) = find_meta_config_in(start_dir) else {
-        return MetaDefaults::default();
-    };
+/// Generate the JSON Schema for .meta / .meta.yaml files.
+///
+/// The schema is derived from `MetaConfig`, `ProjectEntry` and `MetaDefaults`,
+/// so new fields are picked up automatically. Unknown keys are rejected, matching
+/// the `UnknownKey` diagnostics from `validate`. It can be served to editors
+/// (e.g. the VS Code YAML/JSON language servers) for completion and validation.
+///
+/// `extends`, the alias of `include`, is listed as a property of its own; a
+/// file may use one or the other, not both.
+pub fn meta_config_schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(MetaConfig);
+    schema.insert("title".to_string(), ".meta configuration".into());
+    let mut schema = schema.to_value();
+
+    // schemars doesn't emit serde aliases
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        let mut extends = properties["include"].clone();
+        extends["description"] = "Alias of `include`.".into();
+        properties.insert("extends".to_string(), extends);
+    }
+    schema["not"] = serde_json::json!({ "required": ["include", "extends"] });
+    schema
+}
 
-    let config_str = match std::fs::read_to_string(&config_path) {
-        Ok(s) => s,
-        Err(_) => return MetaDefaults::default(),
+/// Write a meta config file in the given format.
+///
+/// Each project entry is written in its most compact form (see
+/// `ProjectEntry::compact`) and projects are sorted by name. The file is
+/// replaced atomically via `store::write_atomic_bytes`.
+///
+/// Fails with `Error::ConfigSerialize` if the config can't be serialized,
+/// or `Error::Io` if the file can't be written.
+pub fn write_meta_config(
+    meta_path: &Path,
+    config: &MetaConfig,
+    format: ConfigFormat,
+) -> crate::Result<()> {
+    let compacted = MetaConfig {
+        projects: config
+            .projects
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.clone().compact(name)))
+            .collect(),
+        ignore: config.ignore.clone(),
+        defaults: config.defaults.clone(),
+        worktrees_dir: config.worktrees_dir.clone(),
+        include: config.include.clone(),
+        url_rewrites: config.url_rewrites.clone(),
+        github_shorthand: config.github_shorthand,
+        profiles: config.profiles.clone(),
     };
 
-    let path_str = config_path.to_string_lossy();
-    let config: MetaConfig = if path_str.ends_with(".yaml") || path_str.ends_with(".yml") {
-        serde_yaml_ng::from_str(&config_str).unwrap_or_default()
-    } else {
-        serde_json::from_str(&config_str).unwrap_or_default()
+    let serialized = match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(&compacted)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        ConfigFormat::Yaml => serde_yaml_ng::to_string(&compacted).map_err(|e| e.to_string()),
     };
+    let content =
+        serialized.map_err(|message| crate::Error::ConfigSerialize { format, message })?;
+
+    crate::store::write_atomic_bytes(meta_path, content.as_bytes())
+}
 
-    config.defaults
+/// Load the resolved defaults for the meta repo in `start_dir`.
+///
+/// Returns `MetaDefaults::default()` if any layer fails to load; use
+/// `resolve_meta_defaults` to see errors and where each setting came from.
+pub fn load_meta_defaults(start_dir: &Path) -> MetaDefaults {
+    resolve_meta_defaults(start_dir)
+        .map(|resolved| resolved.defaults)
+        .unwrap_or_default()
 }
 
 // ============================================================================
@@ -263,23 +769,51 @@ pub struct MetaTreeNode {
 /// if it has its own .meta file. Recursively expands children up to `max_depth`.
 /// Uses cycle detection via path canonicalization.
 ///
+/// Projects matching the `ignore` list of their own .meta file, or of any
+/// .meta above it, are left out of the tree (see [`IgnoreMatcher`]), so they
+/// are also absent from `flatten_meta_tree` and `build_project_map`.
+///
 /// `max_depth` of `None` means unlimited recursion.
 /// `max_depth` of `Some(0)` means no recursion (only top-level projects).
+///
+/// The profile named by `META_PROFILE` applies to every level of the tree;
+/// use `walk_meta_tree_with` to select one explicitly.
 pub fn walk_meta_tree(
     start_dir: &Path,
     max_depth: Option<usize>,
+) -> anyhow::Result<Vec<MetaTreeNode>> {
+    walk_meta_tree_with(start_dir, max_depth, &ParseOptions::default())
+}
+
+/// Like `walk_meta_tree`, parsing every .meta file with `options`.
+///
+/// A nested .meta file that doesn't define the selected profile is walked
+/// unfiltered.
+pub fn walk_meta_tree_with(
+    start_dir: &Path,
+    max_depth: Option<usize>,
+    options: &ParseOptions,
 ) -> anyhow::Result<Vec<MetaTreeNode>> {
     let (config_path, _format) = find_meta_config(start_dir, None)
         .ok_or_else(|| anyhow::anyhow!("No .meta config found in {}", start_dir.display()))?;
 
-    let (projects, _ignore) = parse_meta_config(&config_path)?;
+    let (projects, ignore) = parse_meta_config_with(&config_path, options)?;
     let meta_dir = config_path.parent().unwrap_or(Path::new("."));
 
     let mut visited = std::collections::HashSet::new();
     visited.insert(meta_dir.canonicalize().unwrap_or(meta_dir.to_path_buf()));
 
+    let mut ignores = vec![(meta_dir.to_path_buf(), IgnoreMatcher::new(&ignore))];
     let depth = max_depth.unwrap_or(usize::MAX);
-    Ok(walk_inner(meta_dir, &projects, depth, 0, &mut visited))
+    Ok(walk_inner(
+        meta_dir,
+        &projects,
+        depth,
+        0,
+        &mut visited,
+        &mut ignores,
+        options,
+    ))
 }
 
 /// Flatten a meta tree into fully-qualified path strings.
@@ -308,6 +842,9 @@ fn flatten_inner(nodes: &[MetaTreeNode], prefix: &str, paths: &mut Vec<String>)
 ///
 /// This is useful for looking up projects by their full nested path
 /// (e.g., "vendor/nested-lib" -> resolved filesystem path and project info).
+/// Resolved paths are absolute and normalized (see `ProjectPath::resolve`).
+///
+/// Use `ProjectIndex` to look projects up by `ProjectId` instead.
 pub fn build_project_map(
     nodes: &[MetaTreeNode],
     base_dir: &Path,
@@ -320,7 +857,9 @@ pub fn build_project_map(
         } else {
             format!("{}/{}", prefix, node.info.path)
         };
-        let resolved_path = base_dir.join(&full_path);
+        let resolved_path = ProjectPath::allowing_escape(&full_path)
+            .map(|path| path.resolve(base_dir))
+            .unwrap_or_else(|_| base_dir.join(&full_path));
         map.insert(full_path.clone(), (resolved_path, node.info.clone()));
         // Recurse into children
         map.extend(build_project_map(&node.children, base_dir, &full_path));
@@ -328,6 +867,92 @@ pub fn build_project_map(
     map
 }
 
+// ============================================================================
+// Untracked Directories
+// ============================================================================
+
+/// List directories under a meta root that aren't tracked as projects.
+///
+/// Reads the .meta file in `meta_dir` and scans the directory for
+/// subdirectories that are neither projects nor ignored by its `ignore` list.
+/// Directories that contain tracked projects (e.g. `services` when
+/// `services/api` is a project) are descended into rather than reported;
+/// tracked projects themselves are not scanned. `.git` is always skipped.
+///
+/// Returns `/`-separated paths relative to `meta_dir`, sorted.
+pub fn list_untracked_dirs(meta_dir: &Path) -> anyhow::Result<Vec<String>> {
+    list_untracked_dirs_with(meta_dir, &ConfigSources::default())
+}
+
+fn list_untracked_dirs_with(
+    meta_dir: &Path,
+    sources: &ConfigSources,
+) -> anyhow::Result<Vec<String>> {
+    let (config_path, _format) = find_meta_config_in(meta_dir)
+        .ok_or_else(|| anyhow::anyhow!("No .meta config found in {}", meta_dir.display()))?;
+    // Projects a profile filters out are still tracked
+    let (projects, ignore) =
+        parse_meta_config_with(&config_path, &discover::all_projects(sources))?;
+
+    let tracked: std::collections::HashSet<String> = projects
+        .iter()
+        .map(|p| relative_path_str(Path::new(&p.path)))
+        .collect();
+    let matcher = IgnoreMatcher::new(&ignore);
+
+    let mut untracked = Vec::new();
+    scan_untracked(meta_dir, "", &tracked, &matcher, &mut untracked)?;
+    untracked.sort();
+    Ok(untracked)
+}
+
+fn scan_untracked(
+    dir: &Path,
+    prefix: &str,
+    tracked: &std::collections::HashSet<String>,
+    matcher: &IgnoreMatcher,
+    untracked: &mut Vec<String>,
+) -> crate::Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| crate::Error::io(dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| crate::Error::io(dir, e))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" || !path.is_dir() {
+            continue;
+        }
+
+        let relative = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        if matcher.is_ignored(&relative) || tracked.contains(&relative) {
+            continue;
+        }
+
+        let parent_of_tracked = tracked.iter().any(|t| {
+            t.strip_prefix(&relative)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if parent_of_tracked {
+            scan_untracked(&path, &relative, tracked, matcher, untracked)?;
+        } else {
+            untracked.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Render a relative path with `/` separators, dropping `.` components.
+fn relative_path_str(path: &Path) -> String {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 // ============================================================================
 // Orphan Detection
 // ============================================================================
@@ -360,11 +985,17 @@ pub fn find_parent_meta_config(meta_dir: &Path) -> Option<(PathBuf, ConfigFormat
 /// this directory in its project list (directly or transitively).
 /// Returns `None` if tracked or if there's no parent meta.
 pub fn check_orphan_status(meta_dir: &Path) -> Option<OrphanWarning> {
+    check_orphan_status_with(meta_dir, &ConfigSources::default())
+}
+
+fn check_orphan_status_with(meta_dir: &Path, sources: &ConfigSources) -> Option<OrphanWarning> {
     let (parent_config, parent_format) = find_parent_meta_config(meta_dir)?;
     let parent_meta_dir = parent_config.parent()?;
 
-    // Walk the parent's project tree to see what's tracked
-    let tree = walk_meta_tree(parent_meta_dir, None).ok()?;
+    // Walk the parent's whole project tree (ignoring any profile) to see
+    // what's tracked
+    let options = discover::all_projects(sources);
+    let tree = walk_meta_tree_with(parent_meta_dir, None, &options).ok()?;
     let flat_paths = flatten_meta_tree(&tree);
 
     // Get the relative path from parent to current
@@ -402,12 +1033,23 @@ fn walk_inner(
     max_depth: usize,
     current_depth: usize,
     visited: &mut std::collections::HashSet<PathBuf>,
+    ignores: &mut Vec<(PathBuf, IgnoreMatcher)>,
+    options: &ParseOptions,
 ) -> Vec<MetaTreeNode> {
     let mut nodes = Vec::new();
 
     for project in projects {
         let project_dir = base_dir.join(&project.path);
 
+        // Skip projects ignored by this .meta or any .meta above it
+        if ignores.iter().any(|(root, matcher)| {
+            project_dir
+                .strip_prefix(root)
+                .is_ok_and(|relative| matcher.is_ignored(&relative_path_str(relative)))
+        }) {
+            continue;
+        }
+
         // Check if this project has its own .meta file directly in its directory
         let has_meta = project_dir.is_dir()
             && find_meta_config(&project_dir, None)
@@ -419,14 +1061,21 @@ fn walk_inner(
             let canonical = project_dir.canonicalize().unwrap_or(project_dir.clone());
             if visited.insert(canonical) {
                 if let Some((nested_config_path, _)) = find_meta_config(&project_dir, None) {
-                    if let Ok((nested_projects, _)) = parse_meta_config(&nested_config_path) {
-                        walk_inner(
+                    if let Ok((nested_projects, nested_ignore)) =
+                        parse_meta_config_with(&nested_config_path, options)
+                    {
+                        ignores.push((project_dir.clone(), IgnoreMatcher::new(&nested_ignore)));
+                        let children = walk_inner(
                             &project_dir,
                             &nested_projects,
                             max_depth,
                             current_depth + 1,
                             visited,
-                        )
+                            ignores,
+                            options,
+                        );
+                        ignores.pop();
+                        children
                     } else {
                         vec![]
                     }
@@ -455,6 +1104,22 @@ fn walk_inner(
 mod tests {
     use super::*;
 
+    /// Options that read the user config and overlays from an empty meta
+    /// data directory under `dir` instead of the real one.
+    pub(super) fn isolated(dir: &Path) -> ParseOptions {
+        ParseOptions {
+            sources: ConfigSources::isolated(dir.join(".meta-data")),
+            ..Default::default()
+        }
+    }
+
+    /// `load_meta_defaults` with `isolated` sources.
+    pub(super) fn load_defaults(dir: &Path) -> MetaDefaults {
+        resolve_meta_defaults_with(dir, &isolated(dir))
+            .map(|resolved| resolved.defaults)
+            .unwrap_or_default()
+    }
+
     #[test]
     fn test_walk_meta_tree_no_config() {
         let dir = tempfile::tempdir().unwrap();
@@ -798,6 +1463,7 @@ mod tests {
             provides: vec![],
             depends_on: vec![],
             meta: false,
+            source: None,
         };
         assert!(info.has_no_repo());
 
@@ -809,6 +1475,7 @@ mod tests {
             provides: vec![],
             depends_on: vec![],
             meta: false,
+            source: None,
         };
         assert!(!info_with_repo.has_no_repo());
     }
@@ -855,6 +1522,112 @@ mod tests {
     // Orphan detection tests
     // ============================================================================
 
+    #[test]
+    fn test_walk_meta_tree_honors_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = dir.path().join("vendor");
+        std::fs::create_dir_all(vendor.join("legacy")).unwrap();
+        std::fs::create_dir_all(vendor.join("current")).unwrap();
+        std::fs::create_dir_all(vendor.join("scratch")).unwrap();
+
+        std::fs::write(
+            dir.path().join(".meta"),
+            r#"{
+                "projects": {
+                    "vendor": {"repo": "git@github.com:org/vendor.git", "meta": true},
+                    "old-api": "git@github.com:org/old-api.git"
+                },
+                "ignore": ["old-*", "vendor/legacy"]
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            vendor.join(".meta"),
+            r#"{
+                "projects": {
+                    "legacy": "git@github.com:org/legacy.git",
+                    "current": "git@github.com:org/current.git",
+                    "scratch": "git@github.com:org/scratch.git"
+                },
+                "ignore": ["scratch"]
+            }"#,
+        )
+        .unwrap();
+
+        let tree = walk_meta_tree_with(dir.path(), None, &isolated(dir.path())).unwrap();
+        assert_eq!(flatten_meta_tree(&tree), vec!["vendor", "vendor/current"]);
+
+        let map = build_project_map(&tree, dir.path(), "");
+        assert!(map.contains_key("vendor/current"));
+        assert!(!map.contains_key("vendor/legacy"));
+        assert!(!map.contains_key("vendor/scratch"));
+        assert!(!map.contains_key("old-api"));
+    }
+
+    #[test]
+    fn test_list_untracked_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in [
+            ".git/objects",
+            "api",
+            "services/worker",
+            "services/experiments",
+            "node_modules/pkg",
+            "notes",
+        ] {
+            std::fs::create_dir_all(dir.path().join(sub)).unwrap();
+        }
+        std::fs::write(dir.path().join("README.md"), "").unwrap();
+        std::fs::write(
+            dir.path().join(".meta.yaml"),
+            "projects:\n  api: git@github.com:org/api.git\n  worker:\n    repo: git@github.com:org/worker.git\n    path: services/worker\nignore:\n  - node_modules\n",
+        )
+        .unwrap();
+
+        let untracked = list_untracked_dirs(dir.path()).unwrap();
+        assert_eq!(untracked, vec!["notes", "services/experiments"]);
+    }
+
+    #[test]
+    fn test_entry_points_with_default_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  api: git@github.com:org/api.git\ndefaults:\n  max_concurrency: 3\n",
+        )
+        .unwrap();
+
+        let config = load_meta_config(&meta).unwrap();
+        assert_eq!(config.projects.keys().collect::<Vec<_>>(), vec!["api"]);
+        let resolved = resolve_meta_defaults(dir.path()).unwrap();
+        assert_eq!(resolved.defaults.max_concurrency, Some(3));
+        assert!(overlay_report(&meta).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_untracked_and_orphans_ignore_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("api")).unwrap();
+        std::fs::create_dir_all(dir.path().join("vendor")).unwrap();
+        std::fs::write(dir.path().join("vendor/.meta"), r#"{"projects": {}}"#).unwrap();
+        std::fs::write(
+            dir.path().join(".meta.yaml"),
+            "projects:\n  api: git@github.com:org/api.git\n  vendor:\n    repo: git@github.com:org/vendor.git\n    meta: true\nprofiles:\n  none:\n    filter: nothing\n",
+        )
+        .unwrap();
+        let mut sources = isolated(dir.path()).sources;
+        sources.env = Some(BTreeMap::from([(
+            "META_PROFILE".to_string(),
+            "none".to_string(),
+        )]));
+
+        assert!(list_untracked_dirs_with(dir.path(), &sources)
+            .unwrap()
+            .is_empty());
+        assert!(check_orphan_status_with(&dir.path().join("vendor"), &sources).is_none());
+    }
+
     #[test]
     fn test_find_parent_meta_config_none_at_root() {
         let dir = tempfile::tempdir().unwrap();
@@ -1096,4 +1869,273 @@ mod tests {
         let warning = result.unwrap();
         assert!(matches!(warning.parent_format, ConfigFormat::Yaml));
     }
+
+    #[test]
+    fn test_parse_meta_config_reports_location() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta_path = dir.path().join(".meta.yaml");
+        std::fs::write(&meta_path, "projects:\n  core: [unclosed\n").unwrap();
+
+        match parse_meta_config_with(&meta_path, &isolated(dir.path())) {
+            Err(crate::Error::ConfigParse {
+                path, format, line, ..
+            }) => {
+                assert_eq!(path, meta_path);
+                assert_eq!(format, ConfigFormat::Yaml);
+                assert!(line.is_some());
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+
+        let json_path = dir.path().join(".meta");
+        std::fs::write(&json_path, "{\n  \"projects\": {,}\n}").unwrap();
+        match parse_meta_config_with(&json_path, &isolated(dir.path())) {
+            Err(crate::Error::ConfigParse {
+                line,
+                column,
+                message,
+                ..
+            }) => {
+                assert_eq!(line, Some(2));
+                assert_eq!(column, Some(16));
+                assert!(!message.contains(" at line "));
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_meta_config_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = parse_meta_config_with(&dir.path().join(".meta"), &isolated(dir.path()));
+        assert!(matches!(result, Err(crate::Error::Io { .. })));
+    }
+
+    // ============================================================================
+    // JSON Schema
+    // ============================================================================
+
+    fn schema_keys(object: &serde_json::Value) -> Vec<String> {
+        let mut keys: Vec<String> = object["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn sorted(keys: &[&str]) -> Vec<String> {
+        let mut keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_schema_matches_known_keys() {
+        // validate's unknown-key lists must stay in sync with the Rust types
+        let schema = meta_config_schema();
+        assert_eq!(schema["title"], ".meta configuration");
+        assert_eq!(schema["additionalProperties"], false);
+        let top_level: Vec<&str> = validate::TOP_LEVEL_KEYS
+            .iter()
+            .chain(validate::TOP_LEVEL_ALIASES)
+            .copied()
+            .collect();
+        assert_eq!(schema_keys(&schema), sorted(&top_level));
+        assert_eq!(schema["properties"]["extends"]["type"], "array");
+        assert_eq!(
+            schema_keys(&schema["$defs"]["MetaDefaults"]),
+            sorted(validate::DEFAULTS_KEYS)
+        );
+
+        let variants = schema["$defs"]["ProjectEntry"]["anyOf"].as_array().unwrap();
+        assert_eq!(variants[0]["type"], "string");
+        assert_eq!(variants[1]["additionalProperties"], false);
+        assert_eq!(schema_keys(&variants[1]), sorted(validate::PROJECT_KEYS));
+    }
+
+    // ============================================================================
+    // Writing configs
+    // ============================================================================
+
+    #[test]
+    fn test_project_entry_compact() {
+        let simple = ProjectEntry::Extended {
+            repo: Some("git@github.com:org/core.git".to_string()),
+            path: Some("core".to_string()),
+            tags: vec![],
+            provides: vec![],
+            depends_on: vec![],
+            meta: false,
+        };
+        assert_eq!(
+            simple.compact("core"),
+            ProjectEntry::Simple("git@github.com:org/core.git".to_string())
+        );
+
+        let custom_path = ProjectEntry::Extended {
+            repo: Some("git@github.com:org/core.git".to_string()),
+            path: Some("libs/core".to_string()),
+            tags: vec![],
+            provides: vec![],
+            depends_on: vec![],
+            meta: false,
+        };
+        assert!(matches!(
+            custom_path.compact("core"),
+            ProjectEntry::Extended { path: Some(ref p), .. } if p == "libs/core"
+        ));
+    }
+
+    #[test]
+    fn test_write_meta_config_json_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta_path = dir.path().join(".meta");
+        std::fs::write(
+            &meta_path,
+            r#"{"projects": {
+                "core": {"repo": "git@github.com:org/core.git"},
+                "web": {"repo": "git@github.com:org/web.git", "tags": ["frontend"]}
+            }}"#,
+        )
+        .unwrap();
+
+        let mut config = read_meta_config(&meta_path).unwrap();
+        config.projects.insert(
+            "api".to_string(),
+            ProjectEntry::Simple("git@github.com:org/api.git".to_string()),
+        );
+        write_meta_config(&meta_path, &config, ConfigFormat::Json).unwrap();
+
+        let written = std::fs::read_to_string(&meta_path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&written).unwrap();
+        // Entries with only a repo are written in the simple form
+        assert_eq!(value["projects"]["core"], "git@github.com:org/core.git");
+        assert_eq!(value["projects"]["web"]["tags"][0], "frontend");
+        assert!(value.get("defaults").is_none());
+
+        let (projects, _) = parse_meta_config_with(&meta_path, &isolated(dir.path())).unwrap();
+        let names: Vec<_> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["api", "core", "web"]);
+    }
+
+    #[test]
+    fn test_write_meta_config_yaml_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta_path = dir.path().join(".meta.yaml");
+        let mut config = MetaConfig::default();
+        config.projects.insert(
+            "vendor".to_string(),
+            ProjectEntry::Extended {
+                repo: Some("git@github.com:org/vendor.git".to_string()),
+                path: Some("third_party/vendor".to_string()),
+                tags: vec![],
+                provides: vec![],
+                depends_on: vec![],
+                meta: true,
+            },
+        );
+        config.ignore.push("tmp".to_string());
+        config.defaults.parallel = false;
+
+        write_meta_config(&meta_path, &config, ConfigFormat::from_path(&meta_path)).unwrap();
+
+        let written = std::fs::read_to_string(&meta_path).unwrap();
+        assert!(written.contains("path: third_party/vendor"));
+        assert!(!written.contains("tags"));
+
+        let (projects, ignore) = parse_meta_config_with(&meta_path, &isolated(dir.path())).unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path, "third_party/vendor");
+        assert!(projects[0].meta);
+        assert_eq!(ignore, vec!["tmp"]);
+        assert!(!load_defaults(dir.path()).parallel);
+    }
+
+    #[test]
+    fn test_project_entry_from_info() {
+        let info = ProjectInfo {
+            name: "core".to_string(),
+            path: "core".to_string(),
+            repo: Some("git@github.com:org/core.git".to_string()),
+            tags: vec![],
+            provides: vec![],
+            depends_on: vec![],
+            meta: false,
+            source: None,
+        };
+        assert_eq!(
+            ProjectEntry::from_info(&info),
+            ProjectEntry::Simple("git@github.com:org/core.git".to_string())
+        );
+    }
+
+    #[test]
+    fn test_project_info_from_git_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkout = dir.path().join("services/api");
+        std::fs::create_dir_all(checkout.join(".git")).unwrap();
+        std::fs::write(
+            checkout.join(".git/config"),
+            "[core]\n\tbare = false\n[remote \"origin\"]\n\turl = git@github.com:org/api.git\n",
+        )
+        .unwrap();
+
+        let info = ProjectInfo::from_git_checkout(dir.path(), "./services/api").unwrap();
+        assert_eq!(info.name, "api");
+        assert_eq!(info.path, "services/api");
+        assert_eq!(
+            ProjectEntry::from_info(&info),
+            ProjectEntry::Extended {
+                repo: Some("git@github.com:org/api.git".to_string()),
+                path: Some("services/api".to_string()),
+                tags: vec![],
+                provides: vec![],
+                depends_on: vec![],
+                meta: false,
+            }
+        );
+
+        std::fs::create_dir_all(dir.path().join("plain")).unwrap();
+        assert!(matches!(
+            ProjectInfo::from_git_checkout(dir.path(), "plain"),
+            Err(crate::Error::NotGitRepo { .. })
+        ));
+    }
+
+    #[test]
+    fn test_project_info_from_gitmodules() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(
+            dir.path().join(".git/config"),
+            "[remote \"origin\"]\n\turl = git@github.com:org/mono.git\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join(".gitmodules"),
+            "[submodule \"core\"]\n\tpath = libs/core\n\turl = ../core.git\n\
+             [submodule \"web-core\"]\n\tpath = web/core\n\turl = https://github.com/org/web-core.git\n",
+        )
+        .unwrap();
+
+        let projects = ProjectInfo::from_gitmodules(dir.path()).unwrap();
+        let summary: Vec<(&str, &str, Option<&str>)> = projects
+            .iter()
+            .map(|p| (p.name.as_str(), p.path.as_str(), p.repo.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("core", "libs/core", Some("git@github.com:org/core.git")),
+                (
+                    "web/core",
+                    "web/core",
+                    Some("https://github.com/org/web-core.git")
+                ),
+            ]
+        );
+    }
 }
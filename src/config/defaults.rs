@@ -0,0 +1,319 @@
+//! Layered resolution of `MetaDefaults`.
+//!
+//! Each setting is taken from the last layer that sets it:
+//!
+//! 1. built-in defaults
+//! 2. the user config (`~/.meta/config.yaml`, `defaults:` section)
+//! 3. the repo's .meta file, including `include`d fragments
+//! 4. the selected profile in the .meta file (see `Profile`)
+//! 5. the user's overlay for the meta root (see `overlay_path`)
+//! 6. `META_*` environment variables (`META_PARALLEL`, `META_MAX_CONCURRENCY`,
+//!    `META_FILTER`, `META_SHELL`, `META_TIMEOUT_SECS`, `META_FAIL_FAST`)
+//!
+//! Booleans in the environment accept `true`/`false`, `1`/`0`, `yes`/`no`
+//! and `on`/`off`.
+
+use super::validate::DEFAULTS_KEYS;
+use super::MetaDefaults;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Defaults set by one layer; unset fields leave lower layers' values alone.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PartialDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_fast: Option<bool>,
+}
+
+impl PartialDefaults {
+    /// The settings this layer sets, by key.
+    pub(super) fn values(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(values)) => values,
+            _ => Map::new(),
+        }
+    }
+}
+
+/// Where a resolved default came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "source", rename_all = "snake_case")]
+pub enum DefaultsOrigin {
+    /// Not set anywhere; the built-in default.
+    BuiltIn,
+    /// The user config file.
+    User(PathBuf),
+    /// A .meta file or included fragment.
+    Repo(PathBuf),
+    /// A profile in the .meta file, by name.
+    Profile(String),
+    /// The user's overlay file for the meta root.
+    Overlay(PathBuf),
+    /// An environment variable.
+    Env(String),
+}
+
+impl fmt::Display for DefaultsOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefaultsOrigin::BuiltIn => write!(f, "built-in"),
+            DefaultsOrigin::User(path) => write!(f, "user config {}", path.display()),
+            DefaultsOrigin::Repo(path) => write!(f, "{}", path.display()),
+            DefaultsOrigin::Profile(name) => write!(f, "profile {name}"),
+            DefaultsOrigin::Overlay(path) => write!(f, "overlay {}", path.display()),
+            DefaultsOrigin::Env(name) => write!(f, "environment variable {name}"),
+        }
+    }
+}
+
+/// Resolved defaults with the origin of each setting.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolvedDefaults {
+    pub defaults: MetaDefaults,
+    /// Origin of every `MetaDefaults` key.
+    pub origins: BTreeMap<String, DefaultsOrigin>,
+}
+
+impl ResolvedDefaults {
+    /// Origin of the setting `key` (e.g. `"parallel"`).
+    pub fn origin(&self, key: &str) -> Option<&DefaultsOrigin> {
+        self.origins.get(key)
+    }
+}
+
+/// One setting from one layer.
+pub(super) type Setting = (String, Value, DefaultsOrigin);
+
+/// Settings from a layer that applies as a whole.
+pub(super) fn settings(partial: &PartialDefaults, origin: DefaultsOrigin) -> Vec<Setting> {
+    partial
+        .values()
+        .into_iter()
+        .map(|(key, value)| (key, value, origin.clone()))
+        .collect()
+}
+
+/// Settings from `META_*` environment variables.
+///
+/// Fails with `Error::InvalidEnv` if a variable doesn't parse as its setting's type.
+pub(super) fn env_settings(lookup: impl Fn(&str) -> Option<String>) -> crate::Result<Vec<Setting>> {
+    let mut settings = Vec::new();
+    for key in DEFAULTS_KEYS {
+        let name = format!("META_{}", key.to_ascii_uppercase());
+        let Some(raw) = lookup(&name) else {
+            continue;
+        };
+        let value =
+            parse_env_value(key, raw.trim()).map_err(|message| crate::Error::InvalidEnv {
+                name: name.clone(),
+                value: raw.clone(),
+                message,
+            })?;
+        settings.push((key.to_string(), value, DefaultsOrigin::Env(name)));
+    }
+    Ok(settings)
+}
+
+/// Interpret an environment value for `key`, trying it as a string, then a
+/// boolean, then a number, and keeping the first form the setting accepts.
+fn parse_env_value(key: &str, raw: &str) -> Result<Value, String> {
+    let boolean = match raw.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    };
+    let candidates = [
+        Some(Value::String(raw.to_string())),
+        boolean.map(Value::Bool),
+        raw.parse::<u64>().ok().map(Value::from),
+    ];
+
+    let mut first_error = None;
+    for candidate in candidates.into_iter().flatten() {
+        let single = Value::Object(Map::from_iter([(key.to_string(), candidate.clone())]));
+        match serde_json::from_value::<PartialDefaults>(single) {
+            Ok(_) => return Ok(candidate),
+            Err(e) => {
+                first_error.get_or_insert(e.to_string());
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| format!("invalid value for `{key}`")))
+}
+
+/// Apply settings in order over the built-in defaults.
+///
+/// Settings for keys `MetaDefaults` doesn't have (e.g. a misspelled key in a
+/// .meta file, which `validate` reports) have no effect and get no origin.
+pub(super) fn resolve(settings: impl IntoIterator<Item = Setting>) -> ResolvedDefaults {
+    let mut values = PartialDefaults::default().values();
+    let mut origins: BTreeMap<String, DefaultsOrigin> = DEFAULTS_KEYS
+        .iter()
+        .map(|key| (key.to_string(), DefaultsOrigin::BuiltIn))
+        .collect();
+
+    for (key, value, origin) in settings {
+        if !DEFAULTS_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+        values.insert(key.clone(), value);
+        origins.insert(key, origin);
+    }
+
+    // Every layer was validated against `PartialDefaults`, so this can't fail
+    let defaults = serde_json::from_value(Value::Object(values)).unwrap_or_default();
+    ResolvedDefaults { defaults, origins }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_apply_in_order() {
+        let user = PartialDefaults {
+            parallel: Some(false),
+            shell: Some("/bin/zsh".to_string()),
+            max_concurrency: Some(2),
+            ..Default::default()
+        };
+        let repo = PartialDefaults {
+            max_concurrency: Some(8),
+            fail_fast: Some(true),
+            ..Default::default()
+        };
+        let env = env_settings(|name| match name {
+            "META_MAX_CONCURRENCY" => Some("4".to_string()),
+            "META_PARALLEL" => Some("yes".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        let resolved = resolve(
+            settings(&user, DefaultsOrigin::User("config.yaml".into()))
+                .into_iter()
+                .chain(settings(&repo, DefaultsOrigin::Repo(".meta".into())))
+                .chain(env),
+        );
+
+        assert_eq!(
+            resolved.defaults,
+            MetaDefaults {
+                parallel: true,
+                max_concurrency: Some(4),
+                filter: None,
+                shell: Some("/bin/zsh".to_string()),
+                timeout_secs: None,
+                fail_fast: true,
+            }
+        );
+        assert_eq!(
+            resolved.origin("parallel"),
+            Some(&DefaultsOrigin::Env("META_PARALLEL".to_string()))
+        );
+        assert_eq!(
+            resolved.origin("shell"),
+            Some(&DefaultsOrigin::User("config.yaml".into()))
+        );
+        assert_eq!(
+            resolved.origin("fail_fast"),
+            Some(&DefaultsOrigin::Repo(".meta".into()))
+        );
+        assert_eq!(
+            resolved.origin("timeout_secs"),
+            Some(&DefaultsOrigin::BuiltIn)
+        );
+        assert_eq!(resolved.origins.len(), DEFAULTS_KEYS.len());
+    }
+
+    #[test]
+    fn test_env_values_are_typed() {
+        let env = env_settings(|name| match name {
+            "META_SHELL" => Some("1".to_string()),
+            "META_FAIL_FAST" => Some("0".to_string()),
+            "META_TIMEOUT_SECS" => Some("30".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let resolved = resolve(env);
+        assert_eq!(resolved.defaults.shell.as_deref(), Some("1"));
+        assert!(!resolved.defaults.fail_fast);
+        assert_eq!(resolved.defaults.timeout_secs, Some(30));
+
+        match env_settings(|name| (name == "META_PARALLEL").then(|| "maybe".to_string())) {
+            Err(crate::Error::InvalidEnv { name, value, .. }) => {
+                assert_eq!(name, "META_PARALLEL");
+                assert_eq!(value, "maybe");
+            }
+            other => panic!("expected InvalidEnv, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_partial_defaults_cover_every_key() {
+        let all = PartialDefaults {
+            parallel: Some(true),
+            max_concurrency: Some(1),
+            filter: Some("x".to_string()),
+            shell: Some("sh".to_string()),
+            timeout_secs: Some(1),
+            fail_fast: Some(true),
+        };
+        let mut keys: Vec<String> = all.values().keys().cloned().collect();
+        let mut expected: Vec<String> = DEFAULTS_KEYS.iter().map(|k| k.to_string()).collect();
+        keys.sort();
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_resolve_meta_defaults_reports_repo_origin() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "include: [shared.yaml]\nprojects: {}\ndefaults:\n  max_concurrency: 3\n  paralel: false\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("shared.yaml"),
+            "defaults:\n  timeout_secs: 600\n  max_concurrency: 1\n",
+        )
+        .unwrap();
+
+        let resolved = crate::config::resolve_meta_defaults_with(
+            dir.path(),
+            &crate::config::tests::isolated(dir.path()),
+        )
+        .unwrap();
+        assert_eq!(resolved.defaults.max_concurrency, Some(3));
+        assert_eq!(resolved.defaults.timeout_secs, Some(600));
+        assert_eq!(
+            resolved.origin("max_concurrency"),
+            Some(&DefaultsOrigin::Repo(meta))
+        );
+        assert_eq!(
+            resolved.origin("timeout_secs"),
+            Some(&DefaultsOrigin::Repo(dir.path().join("shared.yaml")))
+        );
+        // The misspelled key has no effect, so it isn't reported
+        assert!(resolved.defaults.parallel);
+        assert_eq!(resolved.origin("paralel"), None);
+        assert_eq!(resolved.origins.len(), DEFAULTS_KEYS.len());
+    }
+}
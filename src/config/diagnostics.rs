@@ -0,0 +1,283 @@
+//! Human-readable diagnostics for malformed .meta files.
+//!
+//! serde reports untagged-enum failures in `ProjectEntry` as "data did not
+//! match any variant", with a position that often points at the end of the
+//! `projects` mapping. When the typed parse fails but the file is otherwise
+//! well-formed, we re-check each section against its expected shape to name
+//! the offending project (and field) and point at its line.
+
+use super::{document, ConfigFormat, MetaDefaults, ProjectEntry};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Build an `Error::ConfigParse` for a failed parse of `source`.
+///
+/// `line`, `column` and `message` are what serde reported; they are replaced
+/// with a more specific explanation when one can be found.
+pub(super) fn parse_error(
+    meta_path: &Path,
+    format: ConfigFormat,
+    source: &str,
+    line: Option<usize>,
+    column: Option<usize>,
+    message: &str,
+) -> crate::Error {
+    // serde appends the position to its messages; it is reported separately
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message, |(message, _)| message);
+
+    let (line, column, message) = match explain(source, format) {
+        Some((Some((line, column)), explanation)) => (Some(line), Some(column), explanation),
+        Some((None, explanation)) => (line, column, explanation),
+        None => (line, column, message.to_string()),
+    };
+
+    crate::Error::ConfigParse {
+        path: meta_path.to_path_buf(),
+        format,
+        line,
+        column,
+        message,
+        snippet: line.map(|line| snippet(source, line, column)),
+    }
+}
+
+/// Build an `Error::ConfigParse` for a YAML settings file outside the .meta
+/// format (user config, overlays), where only serde's message is available.
+pub(super) fn yaml_error(path: &Path, source: &str, error: &serde_yaml_ng::Error) -> crate::Error {
+    let line = error.location().map(|l| l.line());
+    let column = error.location().map(|l| l.column());
+    let message = error.to_string();
+    crate::Error::ConfigParse {
+        path: path.to_path_buf(),
+        format: ConfigFormat::Yaml,
+        line,
+        column,
+        message: message
+            .rsplit_once(" at line ")
+            .map_or(message.as_str(), |(message, _)| message)
+            .to_string(),
+        snippet: line.map(|line| snippet(source, line, column)),
+    }
+}
+
+/// Render the lines around `line` with a caret under `column`:
+///
+/// ```text
+///   2 | projects:
+///   3 |   api: 42
+///     |   ^
+/// ```
+pub(super) fn snippet(source: &str, line: usize, column: Option<usize>) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let line = line.clamp(1, lines.len().max(1));
+    let first = line.saturating_sub(1).max(1);
+    let width = line.to_string().len();
+
+    let mut out = Vec::new();
+    for number in first..=line {
+        let text = lines.get(number - 1).copied().unwrap_or("");
+        out.push(format!("{number:>width$} | {text}"));
+    }
+    if let Some(column) = column {
+        out.push(format!(
+            "{:>width$} | {}^",
+            "",
+            " ".repeat(column.saturating_sub(1))
+        ));
+    }
+    out.join("\n")
+}
+
+/// Find a more specific explanation for a typed-parse failure.
+///
+/// Returns `None` if the source isn't well-formed (the serde syntax error is
+/// already precise) or if no section is found to be at fault.
+fn explain(source: &str, format: ConfigFormat) -> Option<(Option<(usize, usize)>, String)> {
+    let value: Value = match format {
+        ConfigFormat::Json => serde_json::from_str(source).ok()?,
+        ConfigFormat::Yaml => serde_yaml_ng::from_str(source).ok()?,
+    };
+    let locate = |key_path: &[&str]| document::locate(source, format, key_path);
+
+    let Value::Object(root) = &value else {
+        return Some((
+            None,
+            format!(
+                "expected a mapping at the top level, found {}",
+                describe(&value)
+            ),
+        ));
+    };
+
+    match root.get("projects") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(projects)) => {
+            for (name, entry) in projects {
+                if ProjectEntry::deserialize(entry).is_ok() {
+                    continue;
+                }
+                let mut message = format!(
+                    "project `{name}`: expected a git URL string or an object with repo/path/tags"
+                );
+                let mut position = locate(&["projects", name]);
+                match field_problem(entry) {
+                    Some((field, problem)) => {
+                        message.push_str(&format!(" (`{field}` {problem})"));
+                        position = locate(&["projects", name, field]).or(position);
+                    }
+                    None => message.push_str(&format!(", found {}", describe(entry))),
+                }
+                return Some((position, message));
+            }
+        }
+        Some(other) => {
+            return Some((
+                locate(&["projects"]),
+                format!(
+                    "`projects` must be a mapping of project names to entries, found {}",
+                    describe(other)
+                ),
+            ));
+        }
+    }
+
+    if let Some(defaults) = root.get("defaults") {
+        if let Err(e) = MetaDefaults::deserialize(defaults) {
+            return Some((locate(&["defaults"]), format!("`defaults`: {e}")));
+        }
+    }
+
+    for key in ["ignore", "include", "extends"] {
+        let Some(value) = root.get(key) else {
+            continue;
+        };
+        if Vec::<String>::deserialize(value).is_err() {
+            return Some((
+                locate(&[key]),
+                format!(
+                    "`{key}` must be a list of strings, found {}",
+                    describe(value)
+                ),
+            ));
+        }
+    }
+
+    None
+}
+
+/// Check the fields of an extended project entry against their expected types.
+fn field_problem(entry: &Value) -> Option<(&str, String)> {
+    let Value::Object(fields) = entry else {
+        return None;
+    };
+    for (field, value) in fields {
+        let expected = match field.as_str() {
+            "repo" | "path" => matches!(value, Value::String(_) | Value::Null)
+                .then_some(())
+                .ok_or("must be a string"),
+            "tags" | "provides" | "depends_on" => Vec::<String>::deserialize(value)
+                .map(|_| ())
+                .map_err(|_| "must be a list of strings"),
+            "meta" => value
+                .is_boolean()
+                .then_some(())
+                .ok_or("must be true or false"),
+            _ => Ok(()),
+        };
+        if let Err(expected) = expected {
+            return Some((field, format!("{expected}, found {}", describe(value))));
+        }
+    }
+    None
+}
+
+/// Describe the kind of a value for error messages.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => format!("boolean `{b}`"),
+        Value::Number(n) => format!("number `{n}`"),
+        Value::String(_) => "a string".to_string(),
+        Value::Array(_) => "a list".to_string(),
+        Value::Object(_) => "a mapping".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn parse(name: &str, content: &str) -> crate::Error {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        crate::config::parse_meta_config_with(&path, &crate::config::tests::isolated(dir.path()))
+            .unwrap_err()
+    }
+
+    #[test]
+    fn test_untagged_failure_names_project() {
+        let err = parse(
+            ".meta.yaml",
+            "projects:\n  core: git@github.com:org/core.git\n  foo: 42\n",
+        );
+        match err {
+            crate::Error::ConfigParse {
+                line,
+                column,
+                message,
+                snippet,
+                ..
+            } => {
+                assert_eq!(line, Some(3));
+                assert_eq!(column, Some(3));
+                assert_eq!(
+                    message,
+                    "project `foo`: expected a git URL string or an object with repo/path/tags, found number `42`"
+                );
+                assert_eq!(
+                    snippet.unwrap(),
+                    "2 |   core: git@github.com:org/core.git\n3 |   foo: 42\n  |   ^"
+                );
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_untagged_failure_names_field() {
+        let err = parse(
+            ".meta",
+            "{\n  \"projects\": {\n    \"api\": {\n      \"repo\": \"git@github.com:org/api.git\",\n      \"tags\": \"backend\"\n    }\n  }\n}\n",
+        );
+        match err {
+            crate::Error::ConfigParse { line, message, .. } => {
+                assert_eq!(line, Some(5));
+                assert!(message.starts_with("project `api`: expected a git URL string"));
+                assert!(message.ends_with("(`tags` must be a list of strings, found a string)"));
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_keeps_serde_position() {
+        let err = parse(".meta", "{\n  \"projects\": {,}\n}");
+        let text = err.to_string();
+        assert!(text.contains("(line 2, column 16)"));
+        assert!(text.contains("2 |   \"projects\": {,}"));
+    }
+
+    #[test]
+    fn test_defaults_type_error() {
+        let err = parse(".meta.yaml", "projects: {}\ndefaults:\n  parallel: maybe\n");
+        match err {
+            crate::Error::ConfigParse { line, message, .. } => {
+                assert_eq!(line, Some(2));
+                assert!(message.starts_with("`defaults`:"));
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Discovery of git checkouts that no .meta file tracks.
+
+use super::{
+    find_meta_config_in, git, load_meta_config_with, walk_meta_tree_with, ConfigSources,
+    IgnoreMatcher, MetaTreeNode, ParseOptions, ProfileSelection, ProjectEntry, ProjectInfo,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Worktree directory used when a .meta file doesn't set `worktrees_dir`.
+const DEFAULT_WORKTREES_DIR: &str = ".worktrees";
+
+/// Options that see every project: a profile's filter must not make its
+/// excluded projects look untracked.
+pub(super) fn all_projects(sources: &ConfigSources) -> ParseOptions {
+    ParseOptions {
+        strict: false,
+        profile: ProfileSelection::Disabled,
+        sources: sources.clone(),
+    }
+}
+
+/// A git checkout under a meta root that isn't tracked by any .meta file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UntrackedRepo {
+    /// Path of the checkout relative to the meta root, `/`-separated.
+    pub path: String,
+    /// Directory of the closest enclosing .meta file, where the entry belongs.
+    pub meta_dir: PathBuf,
+    /// Suggested project key (the checkout's directory name).
+    pub name: String,
+    /// Suggested entry, with the repo URL read from the checkout's git config.
+    pub entry: ProjectEntry,
+}
+
+/// The settings of one .meta file that affect discovery below it.
+struct Scope {
+    /// Path of the .meta file's directory relative to the meta root ("" for the root).
+    prefix: String,
+    dir: PathBuf,
+    ignore: IgnoreMatcher,
+    worktrees_dir: String,
+}
+
+impl Scope {
+    fn load(dir: &Path, prefix: String, options: &ParseOptions) -> Option<Self> {
+        let (config_path, _format) = find_meta_config_in(dir)?;
+        let config = load_meta_config_with(&config_path, options).ok()?;
+        let worktrees_dir = config
+            .worktrees_dir
+            .as_deref()
+            .unwrap_or(DEFAULT_WORKTREES_DIR)
+            .replace('\\', "/")
+            .trim_start_matches("./")
+            .trim_end_matches('/')
+            .to_string();
+        Some(Scope {
+            prefix,
+            dir: dir.to_path_buf(),
+            ignore: IgnoreMatcher::new(&config.ignore),
+            worktrees_dir,
+        })
+    }
+
+    /// `relative` (from the meta root) relative to this scope, if it's below it.
+    fn local<'a>(&self, relative: &'a str) -> Option<&'a str> {
+        if self.prefix.is_empty() {
+            return Some(relative);
+        }
+        relative.strip_prefix(&self.prefix)?.strip_prefix('/')
+    }
+
+    fn excludes(&self, local: &str) -> bool {
+        self.ignore.is_ignored(local)
+            || local == self.worktrees_dir
+            || local
+                .strip_prefix(&self.worktrees_dir)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Find git checkouts under `meta_root` that no project in the meta tree covers.
+///
+/// Walks the full tree from `walk_meta_tree` and scans the filesystem below
+/// `meta_root` for directories with a `.git` directory or file. Tracked
+/// projects are not scanned, except nested meta repos, whose own .meta
+/// settings then apply below them. Directories matched by an `ignore` pattern
+/// or inside a `worktrees_dir` (default `.worktrees`) are skipped, as are
+/// symlinks.
+///
+/// Results are sorted by path.
+pub fn find_untracked_repos(meta_root: &Path) -> anyhow::Result<Vec<UntrackedRepo>> {
+    find_untracked_repos_with(meta_root, &ConfigSources::default())
+}
+
+fn find_untracked_repos_with(
+    meta_root: &Path,
+    sources: &ConfigSources,
+) -> anyhow::Result<Vec<UntrackedRepo>> {
+    let options = all_projects(sources);
+    let tree = walk_meta_tree_with(meta_root, None, &options)?;
+
+    let mut scopes = Vec::new();
+    scopes.extend(Scope::load(meta_root, String::new(), &options));
+    let mut tracked = HashSet::new();
+    let mut meta_paths = HashSet::new();
+    collect(
+        &tree,
+        meta_root,
+        "",
+        &options,
+        &mut scopes,
+        &mut tracked,
+        &mut meta_paths,
+    );
+
+    let mut found = Vec::new();
+    let mut context = Context {
+        scopes: &scopes,
+        tracked: &tracked,
+        meta_paths: &meta_paths,
+        found: &mut found,
+    };
+    context.scan(meta_root, "")?;
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+fn collect(
+    nodes: &[MetaTreeNode],
+    meta_root: &Path,
+    prefix: &str,
+    options: &ParseOptions,
+    scopes: &mut Vec<Scope>,
+    tracked: &mut HashSet<String>,
+    meta_paths: &mut HashSet<String>,
+) {
+    for node in nodes {
+        let full_path = if prefix.is_empty() {
+            node.info.path.clone()
+        } else {
+            format!("{}/{}", prefix, node.info.path)
+        };
+        let full_path = full_path.trim_start_matches("./").to_string();
+        if node.is_meta {
+            scopes.extend(Scope::load(
+                &meta_root.join(&full_path),
+                full_path.clone(),
+                options,
+            ));
+            meta_paths.insert(full_path.clone());
+        }
+        collect(
+            &node.children,
+            meta_root,
+            &full_path,
+            options,
+            scopes,
+            tracked,
+            meta_paths,
+        );
+        tracked.insert(full_path);
+    }
+}
+
+struct Context<'a> {
+    scopes: &'a [Scope],
+    tracked: &'a HashSet<String>,
+    meta_paths: &'a HashSet<String>,
+    found: &'a mut Vec<UntrackedRepo>,
+}
+
+impl Context<'_> {
+    fn scan(&mut self, dir: &Path, prefix: &str) -> crate::Result<()> {
+        let entries = std::fs::read_dir(dir).map_err(|e| crate::Error::io(dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| crate::Error::io(dir, e))?;
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !is_dir || name == ".git" {
+                continue;
+            }
+
+            let relative = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{prefix}/{name}")
+            };
+            let excluded = self.scopes.iter().any(|scope| {
+                scope
+                    .local(&relative)
+                    .is_some_and(|local| scope.excludes(local))
+            });
+            if excluded {
+                continue;
+            }
+
+            let path = entry.path();
+            if self.tracked.contains(&relative) {
+                if self.meta_paths.contains(&relative) {
+                    self.scan(&path, &relative)?;
+                }
+            } else if git::is_repo(&path) {
+                self.found.push(self.suggest(&path, &relative, name));
+            } else {
+                self.scan(&path, &relative)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn suggest(&self, path: &Path, relative: &str, name: String) -> UntrackedRepo {
+        // The deepest enclosing scope owns the entry; the root scope always matches
+        let scope = self
+            .scopes
+            .iter()
+            .filter(|scope| scope.local(relative).is_some())
+            .max_by_key(|scope| scope.prefix.len());
+        let (meta_dir, local) = match scope {
+            Some(scope) => (scope.dir.clone(), scope.local(relative).unwrap_or(relative)),
+            None => (PathBuf::new(), relative),
+        };
+
+        let info = ProjectInfo {
+            name: name.clone(),
+            path: local.to_string(),
+            repo: git::remote_url(path),
+            ..Default::default()
+        };
+        UntrackedRepo {
+            path: relative.to_string(),
+            meta_dir,
+            name,
+            entry: ProjectEntry::from_info(&info),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_checkout(dir: &Path, url: Option<&str>) {
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        if let Some(url) = url {
+            std::fs::write(
+                dir.join(".git/config"),
+                format!("[remote \"origin\"]\n\turl = {url}\n"),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find_untracked_repos() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join(".meta"),
+            r#"{
+                "projects": {
+                    "api": "git@github.com:org/api.git",
+                    "vendor": {"repo": "git@github.com:org/vendor.git", "meta": true}
+                },
+                "ignore": ["scratch"],
+                "worktrees_dir": "wt"
+            }"#,
+        )
+        .unwrap();
+        git_checkout(&root.join("api"), Some("git@github.com:org/api.git"));
+        // A repo nested inside a tracked (non-meta) project is that project's business
+        git_checkout(&root.join("api/third_party/x"), None);
+        git_checkout(&root.join("web"), Some("git@github.com:org/web.git"));
+        git_checkout(&root.join("tools/cli"), None);
+        git_checkout(&root.join("scratch/tmp-repo"), None);
+        git_checkout(&root.join("wt/api-feature"), None);
+
+        let vendor = root.join("vendor");
+        git_checkout(&vendor, None);
+        std::fs::write(
+            vendor.join(".meta"),
+            r#"{"projects": {"lib": "git@github.com:org/lib.git"}}"#,
+        )
+        .unwrap();
+        git_checkout(&vendor.join("lib"), None);
+        git_checkout(
+            &vendor.join("extra"),
+            Some("https://github.com/org/extra.git"),
+        );
+        git_checkout(&vendor.join(".worktrees/lib-fix"), None);
+
+        let untracked = find_untracked_repos(root).unwrap();
+        let paths: Vec<&str> = untracked.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["tools/cli", "vendor/extra", "web"]);
+
+        let cli = &untracked[0];
+        assert_eq!(cli.name, "cli");
+        assert_eq!(cli.meta_dir, root);
+        assert_eq!(
+            cli.entry,
+            ProjectEntry::Extended {
+                repo: None,
+                path: Some("tools/cli".to_string()),
+                tags: vec![],
+                provides: vec![],
+                depends_on: vec![],
+                meta: false,
+            }
+        );
+
+        let extra = &untracked[1];
+        assert_eq!(extra.meta_dir, vendor);
+        assert_eq!(
+            extra.entry,
+            ProjectEntry::Simple("https://github.com/org/extra.git".to_string())
+        );
+        assert_eq!(
+            untracked[2].entry,
+            ProjectEntry::Simple("git@github.com:org/web.git".to_string())
+        );
+    }
+}
@@ -0,0 +1,1037 @@
+//! Format-preserving edits to existing .meta files.
+//!
+//! `MetaConfigDocument` edits the config source text in place instead of
+//! re-serializing it, so comments, whitespace and key order outside the edited
+//! nodes are left untouched. Every edit is re-parsed before it is accepted, so
+//! an edit can never leave the document in an unparseable state.
+
+use super::{ConfigFormat, MetaConfig, ProjectEntry};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// An editable .meta file (JSON or YAML) that preserves formatting on save.
+#[derive(Debug, Clone)]
+pub struct MetaConfigDocument {
+    path: PathBuf,
+    format: ConfigFormat,
+    source: String,
+}
+
+impl MetaConfigDocument {
+    /// Open a meta config file for editing.
+    ///
+    /// The format is detected from the file extension. Fails if the file can't
+    /// be read or doesn't parse as a meta config.
+    pub fn open(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read meta config file: '{}'", path.display()))?;
+        let doc = Self {
+            path: path.to_path_buf(),
+            format: ConfigFormat::from_path(path),
+            source,
+        };
+        doc.config()
+            .with_context(|| format!("Failed to parse meta config file: {}", path.display()))?;
+        Ok(doc)
+    }
+
+    /// Path of the underlying file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Format of the underlying file.
+    pub fn format(&self) -> ConfigFormat {
+        self.format
+    }
+
+    /// The current (possibly edited) source text.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Parse the current source text into a `MetaConfig`.
+    pub fn config(&self) -> Result<MetaConfig> {
+        parse_source(&self.source, self.format)
+    }
+
+    /// Add a new project entry. Fails if a project with this name already exists.
+    ///
+    /// The entry is appended after the last existing project, in its compact form.
+    pub fn add_project(&mut self, name: &str, entry: &ProjectEntry) -> Result<()> {
+        if self.config()?.projects.contains_key(name) {
+            bail!("Project '{name}' already exists in {}", self.path.display());
+        }
+        let entry = entry.clone().compact(name);
+        let edited = match self.format {
+            ConfigFormat::Json => json::add_project(&self.source, name, &entry)?,
+            ConfigFormat::Yaml => yaml::add_project(&self.source, name, &entry)?,
+        };
+        self.accept(edited)
+    }
+
+    /// Remove a project entry, including comments directly above it.
+    ///
+    /// Returns `false` if no project with this name exists.
+    pub fn remove_project(&mut self, name: &str) -> Result<bool> {
+        if !self.config()?.projects.contains_key(name) {
+            return Ok(false);
+        }
+        let edited = match self.format {
+            ConfigFormat::Json => json::remove_project(&self.source, name)?,
+            ConfigFormat::Yaml => yaml::remove_project(&self.source, name)?,
+        };
+        self.accept(edited)?;
+        Ok(true)
+    }
+
+    /// Replace the tags of an existing project.
+    ///
+    /// A simple (URL-only) entry is converted to the extended form. An empty
+    /// `tags` slice removes the `tags` key.
+    pub fn set_tags(&mut self, name: &str, tags: &[String]) -> Result<()> {
+        if !self.config()?.projects.contains_key(name) {
+            bail!("Project '{name}' not found in {}", self.path.display());
+        }
+        let edited = match self.format {
+            ConfigFormat::Json => json::set_tags(&self.source, name, tags)?,
+            ConfigFormat::Yaml => yaml::set_tags(&self.source, name, tags)?,
+        };
+        self.accept(edited)
+    }
+
+    /// Write the document back to its file atomically.
+    pub fn save(&self) -> Result<()> {
+        crate::store::write_atomic_bytes(&self.path, self.source.as_bytes())
+            .with_context(|| format!("Failed to write meta config file: {}", self.path.display()))
+    }
+
+    /// Replace the source with an edited version, if it still parses.
+    fn accept(&mut self, edited: String) -> Result<()> {
+        parse_source(&edited, self.format).with_context(|| {
+            format!(
+                "Editing {} would produce an invalid config",
+                self.path.display()
+            )
+        })?;
+        self.source = edited;
+        Ok(())
+    }
+}
+
+fn parse_source(source: &str, format: ConfigFormat) -> Result<MetaConfig> {
+    Ok(match format {
+        ConfigFormat::Json => serde_json::from_str(source)?,
+        ConfigFormat::Yaml => serde_yaml_ng::from_str(source)?,
+    })
+}
+
+/// Find the 1-based (line, column) of the key at `key_path` in a config source.
+///
+/// For example `["projects", "api", "tags"]` locates the `tags` key of the
+/// `api` project. Returns `None` if the key can't be found (e.g. in flow-style
+/// YAML or malformed input).
+pub(super) fn locate(
+    source: &str,
+    format: ConfigFormat,
+    key_path: &[&str],
+) -> Option<(usize, usize)> {
+    let offset = match format {
+        ConfigFormat::Json => json::locate(source, key_path)?,
+        ConfigFormat::Yaml => yaml::locate(source, key_path)?,
+    };
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[..offset].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+    Some((line, column))
+}
+
+/// Detect the line ending used by `source`.
+fn line_ending(source: &str) -> &'static str {
+    if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+// ============================================================================
+// JSON
+// ============================================================================
+
+mod json {
+    use super::*;
+
+    /// A `"key": value` member of a JSON object, as byte offsets into the source.
+    pub(super) struct Member {
+        pub key: String,
+        pub key_start: usize,
+        pub value_start: usize,
+        pub value_end: usize,
+    }
+
+    /// A JSON object: the offsets of its braces and its members.
+    pub(super) struct Object {
+        pub open: usize,
+        pub close: usize,
+        pub members: Vec<Member>,
+    }
+
+    impl Object {
+        fn member(&self, key: &str) -> Option<&Member> {
+            self.members.iter().find(|m| m.key == key)
+        }
+    }
+
+    pub(super) fn add_project(source: &str, name: &str, entry: &ProjectEntry) -> Result<String> {
+        let root = parse_object(source, skip_ws(source, 0))?;
+        match root.member("projects") {
+            Some(projects) => {
+                let projects = parse_object(source, projects.value_start)?;
+                let value = serde_json::to_value(entry)?;
+                Ok(insert_member(source, &projects, name, &value))
+            }
+            None => {
+                let mut projects = serde_json::Map::new();
+                projects.insert(name.to_string(), serde_json::to_value(entry)?);
+                Ok(insert_member(
+                    source,
+                    &root,
+                    "projects",
+                    &serde_json::Value::Object(projects),
+                ))
+            }
+        }
+    }
+
+    pub(super) fn remove_project(source: &str, name: &str) -> Result<String> {
+        let projects = projects_object(source)?;
+        Ok(remove_member(source, &projects, name))
+    }
+
+    pub(super) fn set_tags(source: &str, name: &str, tags: &[String]) -> Result<String> {
+        let projects = projects_object(source)?;
+        let Some(member) = projects.member(name) else {
+            bail!("Project '{name}' not found");
+        };
+
+        if source[member.value_start..].starts_with('"') {
+            // Simple entry: replace the URL string with an extended object
+            let url: String = serde_json::from_str(&source[member.value_start..member.value_end])?;
+            let entry = ProjectEntry::Extended {
+                repo: Some(url),
+                path: None,
+                tags: tags.to_vec(),
+                provides: vec![],
+                depends_on: vec![],
+                meta: false,
+            };
+            let indent = line_indent(source, member.key_start);
+            let unit = indent_unit(source, &projects);
+            let rendered = render(
+                &serde_json::to_value(&entry)?,
+                &indent,
+                &unit,
+                line_ending(source),
+            );
+            return Ok(splice(
+                source,
+                member.value_start,
+                member.value_end,
+                &rendered,
+            ));
+        }
+
+        let entry = parse_object(source, member.value_start)?;
+        match entry.member("tags") {
+            Some(_) if tags.is_empty() => Ok(remove_member(source, &entry, "tags")),
+            Some(existing) => Ok(splice(
+                source,
+                existing.value_start,
+                existing.value_end,
+                &serde_json::to_string(tags)?,
+            )),
+            None if tags.is_empty() => Ok(source.to_string()),
+            None => Ok(insert_member(
+                source,
+                &entry,
+                "tags",
+                &serde_json::to_value(tags)?,
+            )),
+        }
+    }
+
+    /// Byte offset of the key at `key_path`.
+    pub(super) fn locate(source: &str, key_path: &[&str]) -> Option<usize> {
+        let mut object = parse_object(source, skip_ws(source, 0)).ok()?;
+        let mut found = None;
+        for (i, key) in key_path.iter().enumerate() {
+            let member = object.member(key)?;
+            found = Some(member.key_start);
+            if i + 1 < key_path.len() {
+                object = parse_object(source, member.value_start).ok()?;
+            }
+        }
+        found
+    }
+
+    fn projects_object(source: &str) -> Result<Object> {
+        let root = parse_object(source, skip_ws(source, 0))?;
+        let Some(projects) = root.member("projects") else {
+            bail!("Config has no 'projects' object");
+        };
+        parse_object(source, projects.value_start)
+    }
+
+    /// Append a member to `object`, matching the indentation of its existing members.
+    fn insert_member(
+        source: &str,
+        object: &Object,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> String {
+        let eol = line_ending(source);
+        let key_json = serde_json::to_string(key).unwrap_or_default();
+        let parent_indent = line_indent(source, object.open);
+        let unit = indent_unit(source, object);
+
+        match object.members.last() {
+            Some(last) => {
+                let multiline = source[object.open..last.key_start].contains('\n');
+                let text = if multiline {
+                    let indent = line_indent(source, last.key_start);
+                    format!(
+                        ",{eol}{indent}{key_json}: {}",
+                        render(value, &indent, &unit, eol)
+                    )
+                } else {
+                    format!(
+                        ", {key_json}: {}",
+                        serde_json::to_string(value).unwrap_or_default()
+                    )
+                };
+                splice(source, last.value_end, last.value_end, &text)
+            }
+            None => {
+                let indent = format!("{parent_indent}{unit}");
+                let text = format!(
+                    "{{{eol}{indent}{key_json}: {}{eol}{parent_indent}}}",
+                    render(value, &indent, &unit, eol)
+                );
+                splice(source, object.open, object.close + 1, &text)
+            }
+        }
+    }
+
+    /// Remove the member `key` from `object` along with its separating comma.
+    fn remove_member(source: &str, object: &Object, key: &str) -> String {
+        let Some(index) = object.members.iter().position(|m| m.key == key) else {
+            return source.to_string();
+        };
+        let member = &object.members[index];
+        if let Some(next) = object.members.get(index + 1) {
+            splice(source, member.key_start, next.key_start, "")
+        } else if index > 0 {
+            let prev = &object.members[index - 1];
+            splice(source, prev.value_end, member.value_end, "")
+        } else {
+            splice(source, object.open, object.close + 1, "{}")
+        }
+    }
+
+    /// Render a JSON value pretty-printed, continuing at the given indentation.
+    fn render(value: &serde_json::Value, indent: &str, unit: &str, eol: &str) -> String {
+        let pretty = serde_json::to_string_pretty(value).unwrap_or_default();
+        pretty
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 {
+                    return line.to_string();
+                }
+                let trimmed = line.trim_start_matches(' ');
+                let depth = (line.len() - trimmed.len()) / 2;
+                format!("{indent}{}{trimmed}", unit.repeat(depth))
+            })
+            .collect::<Vec<_>>()
+            .join(eol)
+    }
+
+    /// Guess the indentation unit from the members of `object` (defaults to two spaces).
+    fn indent_unit(source: &str, object: &Object) -> String {
+        let parent = line_indent(source, object.open);
+        object
+            .members
+            .first()
+            .filter(|m| source[object.open..m.key_start].contains('\n'))
+            .map(|m| line_indent(source, m.key_start))
+            .and_then(|member| member.strip_prefix(&parent).map(str::to_string))
+            .filter(|unit| !unit.is_empty())
+            .unwrap_or_else(|| "  ".to_string())
+    }
+
+    /// Leading whitespace of the line containing byte offset `pos`.
+    fn line_indent(source: &str, pos: usize) -> String {
+        let line_start = source[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        source[line_start..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    fn splice(source: &str, start: usize, end: usize, text: &str) -> String {
+        format!("{}{}{}", &source[..start], text, &source[end..])
+    }
+
+    fn skip_ws(source: &str, mut pos: usize) -> usize {
+        let bytes = source.as_bytes();
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Parse the object starting at `open` (which must be `{`) into member spans.
+    pub(super) fn parse_object(source: &str, open: usize) -> Result<Object> {
+        let bytes = source.as_bytes();
+        if bytes.get(open) != Some(&b'{') {
+            bail!("Expected a JSON object at offset {open}");
+        }
+        let mut members = Vec::new();
+        let mut pos = skip_ws(source, open + 1);
+        if bytes.get(pos) == Some(&b'}') {
+            return Ok(Object {
+                open,
+                close: pos,
+                members,
+            });
+        }
+        loop {
+            let key_start = pos;
+            let key_end = skip_string(source, key_start)?;
+            let key: String = serde_json::from_str(&source[key_start..key_end])?;
+            pos = skip_ws(source, key_end);
+            if bytes.get(pos) != Some(&b':') {
+                bail!("Expected ':' at offset {pos}");
+            }
+            let value_start = skip_ws(source, pos + 1);
+            let value_end = skip_value(source, value_start)?;
+            members.push(Member {
+                key,
+                key_start,
+                value_start,
+                value_end,
+            });
+            pos = skip_ws(source, value_end);
+            match bytes.get(pos) {
+                Some(b',') => pos = skip_ws(source, pos + 1),
+                Some(b'}') => {
+                    return Ok(Object {
+                        open,
+                        close: pos,
+                        members,
+                    })
+                }
+                _ => bail!("Expected ',' or '}}' at offset {pos}"),
+            }
+        }
+    }
+
+    /// Return the offset just past the string starting at `start`.
+    fn skip_string(source: &str, start: usize) -> Result<usize> {
+        let bytes = source.as_bytes();
+        if bytes.get(start) != Some(&b'"') {
+            bail!("Expected a string at offset {start}");
+        }
+        let mut pos = start + 1;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\\' => pos += 2,
+                b'"' => return Ok(pos + 1),
+                _ => pos += 1,
+            }
+        }
+        bail!("Unterminated string at offset {start}")
+    }
+
+    /// Return the offset just past the value starting at `start`.
+    fn skip_value(source: &str, start: usize) -> Result<usize> {
+        let bytes = source.as_bytes();
+        match bytes.get(start) {
+            Some(b'"') => skip_string(source, start),
+            Some(b'{') | Some(b'[') => {
+                let mut depth = 0usize;
+                let mut pos = start;
+                while pos < bytes.len() {
+                    match bytes[pos] {
+                        b'"' => {
+                            pos = skip_string(source, pos)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return Ok(pos + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    pos += 1;
+                }
+                bail!("Unterminated value at offset {start}")
+            }
+            Some(_) => {
+                let mut pos = start;
+                while pos < bytes.len() && !b",}] \t\r\n".contains(&bytes[pos]) {
+                    pos += 1;
+                }
+                Ok(pos)
+            }
+            None => bail!("Unexpected end of input"),
+        }
+    }
+}
+
+// ============================================================================
+// YAML
+// ============================================================================
+
+mod yaml {
+    use super::*;
+
+    /// Source lines with their line endings preserved.
+    struct Lines {
+        lines: Vec<String>,
+        eol: &'static str,
+    }
+
+    impl Lines {
+        fn new(source: &str) -> Self {
+            Self {
+                lines: source.split_inclusive('\n').map(str::to_string).collect(),
+                eol: line_ending(source),
+            }
+        }
+
+        fn finish(self) -> String {
+            self.lines.concat()
+        }
+
+        /// Insert `new_lines` before line `at`, terminating each with the document's line ending.
+        fn insert(&mut self, at: usize, new_lines: Vec<String>) {
+            if at > 0 && !self.lines[at - 1].ends_with('\n') {
+                self.lines[at - 1].push_str(self.eol);
+            }
+            let eol = self.eol;
+            self.lines.splice(
+                at..at,
+                new_lines.into_iter().map(|line| format!("{line}{eol}")),
+            );
+        }
+
+        /// Replace line `at` with `text`, keeping its original line ending.
+        fn replace_line(&mut self, at: usize, text: &str) {
+            let ending = if self.lines[at].ends_with("\r\n") {
+                "\r\n"
+            } else if self.lines[at].ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            self.lines[at] = format!("{text}{ending}");
+        }
+    }
+
+    /// A `key: value` line inside a block mapping.
+    struct KeyLine {
+        key: String,
+        indent: usize,
+        /// Inline value after the colon, without any trailing comment.
+        value: String,
+        /// Trailing comment (including leading whitespace), if any.
+        comment: String,
+    }
+
+    fn content(line: &str) -> &str {
+        line.trim_end_matches(['\n', '\r'])
+    }
+
+    fn indent_of(line: &str) -> usize {
+        line.len() - line.trim_start_matches(' ').len()
+    }
+
+    fn is_blank_or_comment(line: &str) -> bool {
+        let trimmed = line.trim();
+        trimmed.is_empty() || trimmed.starts_with('#')
+    }
+
+    fn is_comment(line: &str) -> bool {
+        line.trim().starts_with('#')
+    }
+
+    /// Split a value into the value itself and a trailing ` # comment`.
+    fn split_comment(value: &str) -> (&str, &str) {
+        let mut quote = None;
+        let bytes = value.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            match (quote, b) {
+                (None, b'\'') | (None, b'"') => quote = Some(b),
+                (Some(q), _) if b == q => quote = None,
+                (None, b'#') if i == 0 || bytes[i - 1] == b' ' || bytes[i - 1] == b'\t' => {
+                    let value_part = value[..i].trim_end();
+                    return (value_part, &value[value_part.len()..]);
+                }
+                _ => {}
+            }
+        }
+        (value.trim_end(), "")
+    }
+
+    /// Parse a `key: value` line. Returns `None` for blank, comment or non-mapping lines.
+    fn parse_key_line(line: &str) -> Option<KeyLine> {
+        let line = content(line);
+        if is_blank_or_comment(line) {
+            return None;
+        }
+        let indent = indent_of(line);
+        let rest = &line[indent..];
+        let (key, after_key) = if let Some(quote @ ('"' | '\'')) = rest.chars().next() {
+            let end = rest[1..].find(quote)? + 1;
+            (rest[1..end].to_string(), &rest[end + 1..])
+        } else {
+            let colon = rest
+                .char_indices()
+                .find(|&(i, c)| c == ':' && rest[i + 1..].chars().next().is_none_or(|n| n == ' '))?
+                .0;
+            (rest[..colon].trim_end().to_string(), &rest[colon..])
+        };
+        let after_colon = after_key.strip_prefix(':')?;
+        let (value, comment) = split_comment(after_colon.trim_start());
+        Some(KeyLine {
+            key,
+            indent,
+            value: value.to_string(),
+            comment: comment.to_string(),
+        })
+    }
+
+    /// End (exclusive) of the block nested under the key on line `at`.
+    ///
+    /// Trailing blank and comment lines are not part of the block.
+    fn block_end(lines: &[String], at: usize) -> usize {
+        let indent = indent_of(&lines[at]);
+        let mut end = at + 1;
+        for (i, line) in lines.iter().enumerate().skip(at + 1) {
+            if is_blank_or_comment(line) {
+                continue;
+            }
+            let line_indent = indent_of(line);
+            // A block sequence may sit at the same indentation as its key
+            let compact_seq = line_indent == indent && line.trim_start().starts_with('-');
+            if line_indent <= indent && !compact_seq {
+                break;
+            }
+            end = i + 1;
+        }
+        end
+    }
+
+    /// Line indices of the keys of the block mapping nested under line `at`.
+    fn child_keys(lines: &[String], at: usize) -> Vec<usize> {
+        let end = block_end(lines, at);
+        let Some(first) = (at + 1..end).find(|&i| !is_blank_or_comment(&lines[i])) else {
+            return vec![];
+        };
+        let child_indent = indent_of(&lines[first]);
+        (first..end)
+            .filter(|&i| !is_blank_or_comment(&lines[i]) && indent_of(&lines[i]) == child_indent)
+            .filter(|&i| parse_key_line(&lines[i]).is_some())
+            .collect()
+    }
+
+    /// Byte offset of the key at `key_path`.
+    pub(super) fn locate(source: &str, key_path: &[&str]) -> Option<usize> {
+        let lines = Lines::new(source).lines;
+        let (first, rest) = key_path.split_first()?;
+        let mut line = lines.iter().position(|line| {
+            parse_key_line(line).is_some_and(|kl| kl.indent == 0 && kl.key == *first)
+        })?;
+        for key in rest {
+            line = child_keys(&lines, line)
+                .into_iter()
+                .find(|&i| parse_key_line(&lines[i]).is_some_and(|kl| kl.key == *key))?;
+        }
+        let offset: usize = lines[..line].iter().map(String::len).sum();
+        Some(offset + indent_of(&lines[line]))
+    }
+
+    /// Line index of the top-level `projects` key, if any.
+    fn projects_line(lines: &[String]) -> Option<usize> {
+        lines.iter().position(|line| {
+            parse_key_line(line).is_some_and(|kl| kl.indent == 0 && kl.key == "projects")
+        })
+    }
+
+    fn find_project(lines: &[String], name: &str) -> Result<(usize, usize)> {
+        let Some(projects) = projects_line(lines) else {
+            bail!("Config has no 'projects' mapping");
+        };
+        let Some(line) = child_keys(lines, projects)
+            .into_iter()
+            .find(|&i| parse_key_line(&lines[i]).is_some_and(|kl| kl.key == name))
+        else {
+            bail!("Project '{name}' not found (flow-style mappings can't be edited in place)");
+        };
+        Ok((projects, line))
+    }
+
+    /// Indentation step used for the projects mapping (defaults to two spaces).
+    fn indent_unit(lines: &[String], projects: usize) -> usize {
+        child_keys(lines, projects)
+            .first()
+            .map(|&i| indent_of(&lines[i]))
+            .filter(|&n| n > 0)
+            .unwrap_or(2)
+    }
+
+    /// Render a YAML value as block lines, indented by `indent` spaces.
+    fn render<T: serde::Serialize>(value: &T, indent: usize) -> Result<Vec<String>> {
+        let rendered = serde_yaml_ng::to_string(value)?;
+        let pad = " ".repeat(indent);
+        Ok(rendered
+            .lines()
+            .map(|line| format!("{pad}{line}"))
+            .collect())
+    }
+
+    /// Render a scalar for use inside a flow sequence.
+    fn flow_scalar(value: &str) -> Result<String> {
+        let plain = serde_yaml_ng::to_string(value)?.trim_end().to_string();
+        if plain.contains([',', '[', ']', '{', '}']) && !plain.starts_with(['\'', '"']) {
+            Ok(serde_json::to_string(value)?)
+        } else {
+            Ok(plain)
+        }
+    }
+
+    fn flow_sequence(items: &[String]) -> Result<String> {
+        let items = items
+            .iter()
+            .map(|item| flow_scalar(item))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("[{}]", items.join(", ")))
+    }
+
+    pub(super) fn add_project(source: &str, name: &str, entry: &ProjectEntry) -> Result<String> {
+        let mut doc = Lines::new(source);
+        let single = BTreeMap::from([(name, entry)]);
+
+        let Some(projects) = projects_line(&doc.lines) else {
+            let mut new_lines = vec!["projects:".to_string()];
+            new_lines.extend(render(&single, 2)?);
+            let at = doc.lines.len();
+            doc.insert(at, new_lines);
+            return Ok(doc.finish());
+        };
+
+        let key_line = parse_key_line(&doc.lines[projects]).unwrap_or(KeyLine {
+            key: "projects".to_string(),
+            indent: 0,
+            value: String::new(),
+            comment: String::new(),
+        });
+        match key_line.value.as_str() {
+            "" | "{}" | "~" | "null" => {}
+            _ => bail!("Flow-style 'projects' mappings can't be edited in place"),
+        }
+        if !key_line.value.is_empty() {
+            doc.replace_line(projects, &format!("projects:{}", key_line.comment));
+        }
+
+        let children = child_keys(&doc.lines, projects);
+        let indent = indent_unit(&doc.lines, projects);
+        let at = match children.last() {
+            Some(&last) => block_end(&doc.lines, last),
+            None => projects + 1,
+        };
+        doc.insert(at, render(&single, indent)?);
+        Ok(doc.finish())
+    }
+
+    pub(super) fn remove_project(source: &str, name: &str) -> Result<String> {
+        let mut doc = Lines::new(source);
+        let (projects, line) = find_project(&doc.lines, name)?;
+        let only_child = child_keys(&doc.lines, projects).len() == 1;
+
+        // Take comment lines directly above the entry with it
+        let mut start = line;
+        while start > projects + 1 && is_comment(&doc.lines[start - 1]) {
+            start -= 1;
+        }
+        let end = block_end(&doc.lines, line);
+        doc.lines.drain(start..end);
+
+        if only_child {
+            let comment = parse_key_line(&doc.lines[projects])
+                .map(|kl| kl.comment)
+                .unwrap_or_default();
+            doc.replace_line(projects, &format!("projects: {{}}{comment}"));
+        }
+        Ok(doc.finish())
+    }
+
+    pub(super) fn set_tags(source: &str, name: &str, tags: &[String]) -> Result<String> {
+        let mut doc = Lines::new(source);
+        let (projects, line) = find_project(&doc.lines, name)?;
+        let entry = parse_key_line(&doc.lines[line]).expect("project line is a key line");
+        let unit = indent_unit(&doc.lines, projects) - indent_of(&doc.lines[projects]);
+        let field_indent = " ".repeat(entry.indent + unit.max(1));
+
+        if !entry.value.is_empty() {
+            if entry.value.starts_with('{') {
+                bail!("Flow-style entry for project '{name}' can't be edited in place");
+            }
+            // Simple entry: convert to the extended form, keeping the URL as written
+            let key_text = &content(&doc.lines[line])[..entry.indent];
+            let key_text = format!("{key_text}{}:{}", quoted_key(name)?, entry.comment);
+            doc.replace_line(line, &key_text);
+            let mut new_lines = vec![format!("{field_indent}repo: {}", entry.value)];
+            if !tags.is_empty() {
+                new_lines.push(format!("{field_indent}tags: {}", flow_sequence(tags)?));
+            }
+            doc.insert(line + 1, new_lines);
+            return Ok(doc.finish());
+        }
+
+        let fields = child_keys(&doc.lines, line);
+        let field_indent = fields
+            .first()
+            .map(|&i| " ".repeat(indent_of(&doc.lines[i])))
+            .unwrap_or(field_indent);
+        let existing = fields
+            .iter()
+            .copied()
+            .find(|&i| parse_key_line(&doc.lines[i]).is_some_and(|kl| kl.key == "tags"));
+
+        match existing {
+            Some(tags_line) => {
+                let end = block_end(&doc.lines, tags_line);
+                if tags.is_empty() {
+                    doc.lines.drain(tags_line..end);
+                    return Ok(doc.finish());
+                }
+                let old = parse_key_line(&doc.lines[tags_line]).expect("tags line is a key line");
+                if old.value.is_empty() && end > tags_line + 1 {
+                    // Block sequence: keep the block style and item indentation
+                    let item_indent = (tags_line + 1..end)
+                        .find(|&i| !is_blank_or_comment(&doc.lines[i]))
+                        .map(|i| indent_of(&doc.lines[i]))
+                        .unwrap_or(field_indent.len());
+                    doc.lines.drain(tags_line + 1..end);
+                    doc.insert(tags_line + 1, render(&tags, item_indent)?);
+                } else {
+                    let text = format!(
+                        "{field_indent}tags: {}{}",
+                        flow_sequence(tags)?,
+                        old.comment
+                    );
+                    doc.lines.drain(tags_line + 1..end);
+                    doc.replace_line(tags_line, &text);
+                }
+            }
+            None if tags.is_empty() => {}
+            None => {
+                let at = block_end(&doc.lines, line);
+                doc.insert(
+                    at,
+                    vec![format!("{field_indent}tags: {}", flow_sequence(tags)?)],
+                );
+            }
+        }
+        Ok(doc.finish())
+    }
+
+    /// Render a mapping key, quoting it if needed.
+    fn quoted_key(key: &str) -> Result<String> {
+        Ok(serde_yaml_ng::to_string(key)?.trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(dir: &Path, name: &str, content: &str) -> MetaConfigDocument {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        MetaConfigDocument::open(&path).unwrap()
+    }
+
+    #[test]
+    fn test_yaml_add_project_preserves_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(
+            dir.path(),
+            ".meta.yaml",
+            "# Shared platform repos\nprojects:\n  # The API\n  zeta: git@github.com:org/zeta.git\n  alpha:\n    repo: git@github.com:org/alpha.git # primary\n\nignore:\n  - tmp\n",
+        );
+        doc.add_project(
+            "web",
+            &ProjectEntry::Simple("git@github.com:org/web.git".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            doc.as_str(),
+            "# Shared platform repos\nprojects:\n  # The API\n  zeta: git@github.com:org/zeta.git\n  alpha:\n    repo: git@github.com:org/alpha.git # primary\n  web: git@github.com:org/web.git\n\nignore:\n  - tmp\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_add_project_to_empty_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(dir.path(), ".meta.yaml", "projects: {}\n");
+        doc.add_project(
+            "core",
+            &ProjectEntry::Extended {
+                repo: Some("git@github.com:org/core.git".to_string()),
+                path: Some("libs/core".to_string()),
+                tags: vec![],
+                provides: vec![],
+                depends_on: vec![],
+                meta: false,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            doc.as_str(),
+            "projects:\n  core:\n    repo: git@github.com:org/core.git\n    path: libs/core\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_remove_project_with_comment() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(
+            dir.path(),
+            ".meta.yaml",
+            "projects:\n  # The API\n  api:\n    repo: git@github.com:org/api.git\n    tags: [backend]\n  web: git@github.com:org/web.git # site\n",
+        );
+        assert!(doc.remove_project("api").unwrap());
+        assert!(!doc.remove_project("missing").unwrap());
+        assert_eq!(
+            doc.as_str(),
+            "projects:\n  web: git@github.com:org/web.git # site\n"
+        );
+
+        assert!(doc.remove_project("web").unwrap());
+        assert_eq!(doc.as_str(), "projects: {}\n");
+    }
+
+    #[test]
+    fn test_yaml_set_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(
+            dir.path(),
+            ".meta.yaml",
+            "projects:\n  web: git@github.com:org/web.git # site\n  api:\n    repo: git@github.com:org/api.git\n    tags:\n      - backend\n    meta: false\n",
+        );
+        doc.set_tags("web", &["frontend".to_string(), "team:web".to_string()])
+            .unwrap();
+        doc.set_tags("api", &["backend".to_string(), "go".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            doc.as_str(),
+            "projects:\n  web: # site\n    repo: git@github.com:org/web.git\n    tags: [frontend, team:web]\n  api:\n    repo: git@github.com:org/api.git\n    tags:\n      - backend\n      - go\n    meta: false\n"
+        );
+
+        doc.set_tags("api", &[]).unwrap();
+        let config = doc.config().unwrap();
+        match &config.projects["api"] {
+            ProjectEntry::Extended { tags, .. } => assert!(tags.is_empty()),
+            other => panic!("unexpected entry: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_json_edits_preserve_order_and_indentation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(
+            dir.path(),
+            ".meta",
+            "{\n    \"projects\": {\n        \"zeta\": \"git@github.com:org/zeta.git\",\n        \"alpha\": {\n            \"repo\": \"git@github.com:org/alpha.git\"\n        }\n    },\n    \"ignore\": [\"tmp\"]\n}\n",
+        );
+        doc.add_project(
+            "web",
+            &ProjectEntry::Simple("git@github.com:org/web.git".to_string()),
+        )
+        .unwrap();
+        doc.set_tags("alpha", &["core".to_string()]).unwrap();
+        assert!(doc.remove_project("zeta").unwrap());
+
+        assert_eq!(
+            doc.as_str(),
+            "{\n    \"projects\": {\n        \"alpha\": {\n            \"repo\": \"git@github.com:org/alpha.git\",\n            \"tags\": [\n                \"core\"\n            ]\n        },\n        \"web\": \"git@github.com:org/web.git\"\n    },\n    \"ignore\": [\"tmp\"]\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_set_tags_on_simple_entry_and_remove_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(
+            dir.path(),
+            ".meta",
+            r#"{"projects": {"core": "git@github.com:org/core.git"}}"#,
+        );
+        doc.set_tags("core", &["lib".to_string()]).unwrap();
+        let config = doc.config().unwrap();
+        match &config.projects["core"] {
+            ProjectEntry::Extended { repo, tags, .. } => {
+                assert_eq!(repo.as_deref(), Some("git@github.com:org/core.git"));
+                assert_eq!(tags, &vec!["lib".to_string()]);
+            }
+            other => panic!("unexpected entry: {other:?}"),
+        }
+
+        assert!(doc.remove_project("core").unwrap());
+        assert_eq!(doc.as_str(), r#"{"projects": {}}"#);
+    }
+
+    #[test]
+    fn test_locate_keys() {
+        let yaml = "# header\nprojects:\n  api:\n    repo: x\n    tags: [a]\n";
+        assert_eq!(
+            locate(yaml, ConfigFormat::Yaml, &["projects", "api", "tags"]),
+            Some((5, 5))
+        );
+        assert_eq!(locate(yaml, ConfigFormat::Yaml, &["projects", "web"]), None);
+
+        let json = "{\n  \"projects\": {\n    \"api\": \"x\"\n  }\n}";
+        assert_eq!(
+            locate(json, ConfigFormat::Json, &["projects", "api"]),
+            Some((3, 5))
+        );
+    }
+
+    #[test]
+    fn test_add_existing_project_fails_and_save_writes_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut doc = open(dir.path(), ".meta", "{\"projects\": {}}\n");
+        let entry = ProjectEntry::Simple("git@github.com:org/core.git".to_string());
+        doc.add_project("core", &entry).unwrap();
+        assert!(doc.add_project("core", &entry).is_err());
+        doc.save().unwrap();
+
+        let (projects, _) = crate::config::parse_meta_config_with(
+            doc.path(),
+            &crate::config::tests::isolated(dir.path()),
+        )
+        .unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].name, "core");
+    }
+}
@@ -0,0 +1,311 @@
+//! Minimal, read-only access to a checkout's git metadata.
+//!
+//! Only what's needed to describe a repo in a .meta file: locating the git
+//! directory and reading remote URLs from its `config`. This deliberately
+//! doesn't shell out to `git` or support includes/conditional config.
+
+use std::path::{Path, PathBuf};
+
+/// Whether `dir` is the root of a git checkout (has a `.git` directory or file).
+pub(super) fn is_repo(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Resolve the git directory of the checkout at `dir`.
+///
+/// Handles both a `.git` directory and a `.git` file containing
+/// `gitdir: <path>` (submodules and worktrees), relative to `dir`.
+pub(super) fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = std::fs::read_to_string(&dot_git).ok()?;
+    let target = content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gitdir:"))?
+        .trim();
+    Some(dir.join(target))
+}
+
+/// Read the URL of the preferred remote of the checkout at `dir`.
+///
+/// Prefers `origin`; otherwise uses the first remote in the config file.
+pub(super) fn remote_url(dir: &Path) -> Option<String> {
+    let remotes = remotes(&git_dir(dir)?);
+    remotes
+        .iter()
+        .find(|(name, _)| name == "origin")
+        .or_else(|| remotes.first())
+        .map(|(_, url)| url.clone())
+}
+
+/// Read `(name, url)` for each remote in `<git_dir>/config`, in file order.
+///
+/// Worktree git dirs keep their config in the main repo; `commondir` is
+/// followed when present.
+pub(super) fn remotes(git_dir: &Path) -> Vec<(String, String)> {
+    let config_dir = std::fs::read_to_string(git_dir.join("commondir"))
+        .map(|common| git_dir.join(common.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf());
+    std::fs::read_to_string(config_dir.join("config"))
+        .map(|content| parse_remotes(&content))
+        .unwrap_or_default()
+}
+
+fn parse_remotes(content: &str) -> Vec<(String, String)> {
+    let mut remotes: Vec<(String, String)> = Vec::new();
+    for entry in parse_config(content) {
+        if entry.section == "remote" && entry.key == "url" {
+            if let Some(name) = entry.subsection {
+                if !remotes.iter().any(|(existing, _)| *existing == name) {
+                    remotes.push((name, entry.value));
+                }
+            }
+        }
+    }
+    remotes
+}
+
+/// A submodule declared in a `.gitmodules` file.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Submodule {
+    /// Submodule name from `[submodule "<name>"]`.
+    pub name: String,
+    /// Checkout path relative to the superproject root.
+    pub path: String,
+    /// Remote URL as written; may be relative to the superproject's remote.
+    pub url: Option<String>,
+}
+
+/// Parse a `.gitmodules` file. Submodules without a `path` are skipped.
+pub(super) fn parse_gitmodules(content: &str) -> Vec<Submodule> {
+    let mut submodules: Vec<(String, Option<String>, Option<String>)> = Vec::new();
+    for entry in parse_config(content) {
+        let Some(name) = entry.subsection.filter(|_| entry.section == "submodule") else {
+            continue;
+        };
+        let index = match submodules.iter().position(|(n, _, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                submodules.push((name, None, None));
+                submodules.len() - 1
+            }
+        };
+        match entry.key.as_str() {
+            "path" => submodules[index].1 = Some(entry.value),
+            "url" => submodules[index].2 = Some(entry.value),
+            _ => {}
+        }
+    }
+    submodules
+        .into_iter()
+        .filter_map(|(name, path, url)| {
+            let path = path?.replace('\\', "/").trim_end_matches('/').to_string();
+            Some(Submodule { name, path, url })
+        })
+        .collect()
+}
+
+/// Resolve a submodule URL that is relative to the superproject's remote.
+///
+/// Like git, `../other.git` relative to `git@github.com:org/super.git` is
+/// `git@github.com:org/other.git`. Absolute URLs are returned unchanged, as
+/// are relative ones when the superproject has no remote.
+pub(super) fn resolve_submodule_url(url: &str, superproject: Option<&str>) -> String {
+    let (Some(base), true) = (
+        superproject,
+        url.starts_with("./") || url.starts_with("../"),
+    ) else {
+        return url.to_string();
+    };
+
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut rest = url;
+    loop {
+        if let Some(next) = rest.strip_prefix("./") {
+            rest = next;
+        } else if let Some(next) = rest.strip_prefix("../") {
+            rest = next;
+            // Drop the last component, stopping at the host separator of
+            // scp-like URLs (`host:org/repo`) or the `//` of full URLs
+            match base.rfind(['/', ':']) {
+                Some(cut) if base[cut..].starts_with(':') => base.truncate(cut + 1),
+                Some(cut) if !base[..cut].ends_with('/') => base.truncate(cut),
+                _ => {}
+            }
+        } else {
+            break;
+        }
+    }
+    let separator = if base.ends_with(':') { "" } else { "/" };
+    format!("{base}{separator}{rest}")
+}
+
+/// One `key = value` line with its enclosing section.
+struct ConfigEntry {
+    /// Lower-cased section name, e.g. `remote`.
+    section: String,
+    /// Quoted subsection, e.g. `origin` in `[remote "origin"]`.
+    subsection: Option<String>,
+    /// Lower-cased key.
+    key: String,
+    value: String,
+}
+
+/// Parse the subset of git's config syntax used by `config` and `.gitmodules`.
+fn parse_config(content: &str) -> Vec<ConfigEntry> {
+    let mut entries = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.split(']').next().unwrap_or("").trim();
+            let (name, subsection) = match header.split_once(char::is_whitespace) {
+                Some((name, subsection)) => (
+                    name,
+                    subsection
+                        .trim()
+                        .strip_prefix('"')
+                        .and_then(|s| s.strip_suffix('"'))
+                        .map(str::to_string),
+                ),
+                None => (header, None),
+            };
+            section = Some((name.to_ascii_lowercase(), subsection));
+            continue;
+        }
+        let Some((name, subsection)) = &section else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        entries.push(ConfigEntry {
+            section: name.clone(),
+            subsection: subsection.clone(),
+            key: key.trim().to_ascii_lowercase(),
+            value: value.trim().trim_matches('"').to_string(),
+        });
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remotes() {
+        let config = r#"
+[core]
+	bare = false
+[remote "upstream"]
+	url = https://github.com/upstream/api.git
+	fetch = +refs/heads/*:refs/remotes/upstream/*
+[branch "main"]
+	remote = origin
+[remote "origin"]
+	url = git@github.com:org/api.git
+"#;
+        assert_eq!(
+            parse_remotes(config),
+            vec![
+                (
+                    "upstream".to_string(),
+                    "https://github.com/upstream/api.git".to_string()
+                ),
+                (
+                    "origin".to_string(),
+                    "git@github.com:org/api.git".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_gitmodules() {
+        let content = r#"
+[submodule "libs/core"]
+	path = libs/core
+	url = git@github.com:org/core.git
+[submodule "docs"]
+	path = documentation/
+	url = ../docs.git
+	branch = main
+[submodule "broken"]
+	url = git@github.com:org/broken.git
+"#;
+        let submodules = parse_gitmodules(content);
+        assert_eq!(submodules.len(), 2);
+        assert_eq!(submodules[0].name, "libs/core");
+        assert_eq!(submodules[0].path, "libs/core");
+        assert_eq!(submodules[1].path, "documentation");
+        assert_eq!(submodules[1].url.as_deref(), Some("../docs.git"));
+    }
+
+    #[test]
+    fn test_resolve_submodule_url() {
+        let cases = [
+            (
+                "../docs.git",
+                "git@github.com:org/super.git",
+                "git@github.com:org/docs.git",
+            ),
+            (
+                "../../other/x.git",
+                "git@github.com:org/super.git",
+                "git@github.com:other/x.git",
+            ),
+            (
+                "../docs.git",
+                "https://github.com/org/super.git",
+                "https://github.com/org/docs.git",
+            ),
+            (
+                "./sub.git",
+                "https://github.com/org/super",
+                "https://github.com/org/super/sub.git",
+            ),
+            (
+                "git@github.com:org/abs.git",
+                "https://github.com/org/super",
+                "git@github.com:org/abs.git",
+            ),
+        ];
+        for (url, base, expected) in cases {
+            assert_eq!(
+                resolve_submodule_url(url, Some(base)),
+                expected,
+                "{url} from {base}"
+            );
+        }
+        assert_eq!(resolve_submodule_url("../docs.git", None), "../docs.git");
+    }
+
+    #[test]
+    fn test_remote_url_follows_gitdir_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let modules = dir.path().join("parent/.git/modules/lib");
+        std::fs::create_dir_all(&modules).unwrap();
+        std::fs::write(
+            modules.join("config"),
+            "[remote \"fork\"]\n\turl = git@github.com:me/lib.git\n",
+        )
+        .unwrap();
+
+        let checkout = dir.path().join("parent/lib");
+        std::fs::create_dir_all(&checkout).unwrap();
+        std::fs::write(checkout.join(".git"), "gitdir: ../.git/modules/lib\n").unwrap();
+
+        assert!(is_repo(&checkout));
+        assert_eq!(
+            remote_url(&checkout).as_deref(),
+            Some("git@github.com:me/lib.git")
+        );
+    }
+}
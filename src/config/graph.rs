@@ -0,0 +1,511 @@
+//! Dependency graph over `ProjectInfo.depends_on` and `provides`.
+//!
+//! A `depends_on` entry resolves to the project with that name or, failing
+//! that, to every project that lists it in `provides`. Entries that resolve to
+//! nothing are kept in `unresolved()` rather than failing the build, so
+//! callers can decide whether a missing dependency is fatal.
+
+use super::{MetaDefaults, MetaTreeNode, ProjectInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// A `depends_on` entry that matched no project name or `provides` capability.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnresolvedDependency {
+    /// The project declaring the dependency.
+    pub project: String,
+    /// The `depends_on` entry that could not be resolved.
+    pub dependency: String,
+}
+
+/// A group of projects whose dependencies are all satisfied by earlier levels,
+/// so they can run concurrently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionLevel {
+    /// Project names in this level.
+    pub projects: Vec<String>,
+}
+
+/// The order in which to run commands across projects, as a sequence of levels.
+///
+/// Serializable so the CLI can show it (e.g. with `--dry-run`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    /// Whether projects within a level run concurrently.
+    pub parallel: bool,
+    /// Maximum number of projects of a level to run at once, if any. The
+    /// executor starts another project of the level whenever one finishes;
+    /// levels are not split, since that would add waits the dependencies
+    /// don't require.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    pub levels: Vec<ExecutionLevel>,
+}
+
+/// Directed graph of projects, with edges from each project to its dependencies.
+///
+/// Project names are expected to be unique. When a tree contains duplicate
+/// names, name lookups resolve to the first project in walk order.
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    projects: Vec<ProjectInfo>,
+    index: HashMap<String, usize>,
+    /// `edges[i]` are the indices project `i` depends on.
+    edges: Vec<Vec<usize>>,
+    /// `reverse[i]` are the indices that depend on project `i`.
+    reverse: Vec<Vec<usize>>,
+    unresolved: Vec<UnresolvedDependency>,
+}
+
+impl DependencyGraph {
+    /// Build a graph from a flat list of projects (e.g. from `parse_meta_config`).
+    pub fn from_projects(projects: Vec<ProjectInfo>) -> Self {
+        let mut index = HashMap::new();
+        for (i, project) in projects.iter().enumerate() {
+            index.entry(project.name.clone()).or_insert(i);
+        }
+
+        let mut providers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, project) in projects.iter().enumerate() {
+            for capability in &project.provides {
+                providers.entry(capability).or_default().push(i);
+            }
+        }
+
+        let mut edges = vec![Vec::new(); projects.len()];
+        let mut reverse = vec![Vec::new(); projects.len()];
+        let mut unresolved = Vec::new();
+        for (i, project) in projects.iter().enumerate() {
+            let mut targets = BTreeSet::new();
+            for dependency in &project.depends_on {
+                if let Some(&target) = index.get(dependency) {
+                    targets.insert(target);
+                    continue;
+                }
+                let provided: Vec<usize> = providers
+                    .get(dependency.as_str())
+                    .map(|p| p.iter().copied().filter(|&p| p != i).collect())
+                    .unwrap_or_default();
+                if provided.is_empty() {
+                    unresolved.push(UnresolvedDependency {
+                        project: project.name.clone(),
+                        dependency: dependency.clone(),
+                    });
+                }
+                targets.extend(provided);
+            }
+            for &target in &targets {
+                reverse[target].push(i);
+            }
+            edges[i] = targets.into_iter().collect();
+        }
+
+        Self {
+            projects,
+            index,
+            edges,
+            reverse,
+            unresolved,
+        }
+    }
+
+    /// Build a graph from a meta tree (e.g. from `walk_meta_tree`), including nested children.
+    pub fn from_tree(nodes: &[MetaTreeNode]) -> Self {
+        fn collect(nodes: &[MetaTreeNode], out: &mut Vec<ProjectInfo>) {
+            for node in nodes {
+                out.push(node.info.clone());
+                collect(&node.children, out);
+            }
+        }
+        let mut projects = Vec::new();
+        collect(nodes, &mut projects);
+        Self::from_projects(projects)
+    }
+
+    /// All projects in the graph, in the order they were given.
+    pub fn projects(&self) -> &[ProjectInfo] {
+        &self.projects
+    }
+
+    /// Look up a project by name.
+    pub fn get(&self, name: &str) -> Option<&ProjectInfo> {
+        self.index.get(name).map(|&i| &self.projects[i])
+    }
+
+    /// `depends_on` entries that matched no project or capability.
+    pub fn unresolved(&self) -> &[UnresolvedDependency] {
+        &self.unresolved
+    }
+
+    /// Direct dependencies of `name`.
+    pub fn dependencies(&self, name: &str) -> Vec<&ProjectInfo> {
+        self.neighbours(name, &self.edges)
+    }
+
+    /// Projects that directly depend on `name` (reverse dependencies).
+    pub fn dependents(&self, name: &str) -> Vec<&ProjectInfo> {
+        self.neighbours(name, &self.reverse)
+    }
+
+    /// All projects `name` depends on, directly or indirectly.
+    pub fn transitive_dependencies(&self, name: &str) -> Vec<&ProjectInfo> {
+        self.closure(name, &self.edges)
+    }
+
+    /// All projects that depend on `name`, directly or indirectly.
+    pub fn transitive_dependents(&self, name: &str) -> Vec<&ProjectInfo> {
+        self.closure(name, &self.reverse)
+    }
+
+    /// Order projects so every project comes after its dependencies.
+    ///
+    /// Ties are broken by input order, so the result is deterministic.
+    /// Fails with `Error::DependencyCycle` if the graph has a cycle.
+    pub fn topological_order(&self) -> crate::Result<Vec<&ProjectInfo>> {
+        Ok(self
+            .sorted_indices()?
+            .into_iter()
+            .map(|i| &self.projects[i])
+            .collect())
+    }
+
+    /// Split projects into levels: level 0 has no dependencies, and every
+    /// project sits one level after its deepest dependency.
+    ///
+    /// Within a level, projects keep their input order.
+    /// Fails with `Error::DependencyCycle` if the graph has a cycle.
+    pub fn levels(&self) -> crate::Result<Vec<Vec<&ProjectInfo>>> {
+        let mut depth = vec![0usize; self.projects.len()];
+        let mut levels: Vec<Vec<usize>> = Vec::new();
+        for i in self.sorted_indices()? {
+            depth[i] = self.edges[i]
+                .iter()
+                .map(|&dependency| depth[dependency] + 1)
+                .max()
+                .unwrap_or(0);
+            if levels.len() <= depth[i] {
+                levels.resize(depth[i] + 1, Vec::new());
+            }
+            levels[depth[i]].push(i);
+        }
+        Ok(levels
+            .into_iter()
+            .map(|mut level| {
+                level.sort_unstable();
+                level.into_iter().map(|i| &self.projects[i]).collect()
+            })
+            .collect())
+    }
+
+    /// Build an execution plan honoring `defaults.parallel` and an optional
+    /// concurrency limit (`defaults.max_concurrency` if `None`).
+    ///
+    /// With `parallel: false`, every project gets its own level, in level
+    /// order. Otherwise levels are kept whole and the limit is recorded in
+    /// `ExecutionPlan::max_concurrency` for the executor to apply.
+    pub fn execution_plan(
+        &self,
+        defaults: &MetaDefaults,
+        max_concurrency: Option<usize>,
+    ) -> crate::Result<ExecutionPlan> {
+        let max_concurrency = max_concurrency.or(defaults.max_concurrency);
+        let names = |projects: &[&ProjectInfo]| ExecutionLevel {
+            projects: projects.iter().map(|p| p.name.clone()).collect(),
+        };
+
+        let levels = self.levels()?;
+        let levels = if defaults.parallel {
+            levels.iter().map(|level| names(level)).collect()
+        } else {
+            levels
+                .iter()
+                .flat_map(|level| level.chunks(1).map(names).collect::<Vec<_>>())
+                .collect()
+        };
+
+        Ok(ExecutionPlan {
+            parallel: defaults.parallel,
+            max_concurrency,
+            levels,
+        })
+    }
+
+    /// Find a dependency cycle, returned as the project names along the cycle
+    /// with the first name repeated at the end (e.g. `["a", "b", "a"]`).
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Unvisited,
+            OnStack,
+            Done,
+        }
+
+        let mut state = vec![State::Unvisited; self.projects.len()];
+        for start in 0..self.projects.len() {
+            if state[start] != State::Unvisited {
+                continue;
+            }
+            // Iterative DFS: (node, next edge to explore)
+            let mut stack = vec![(start, 0usize)];
+            state[start] = State::OnStack;
+            while let Some(&(node, next)) = stack.last() {
+                if let Some(&target) = self.edges[node].get(next) {
+                    if let Some(top) = stack.last_mut() {
+                        top.1 += 1;
+                    }
+                    match state[target] {
+                        State::Unvisited => {
+                            state[target] = State::OnStack;
+                            stack.push((target, 0));
+                        }
+                        State::OnStack => {
+                            let from = stack.iter().position(|&(n, _)| n == target)?;
+                            let mut cycle: Vec<String> = stack[from..]
+                                .iter()
+                                .map(|&(n, _)| self.projects[n].name.clone())
+                                .collect();
+                            cycle.push(self.projects[target].name.clone());
+                            return Some(cycle);
+                        }
+                        State::Done => {}
+                    }
+                } else {
+                    state[node] = State::Done;
+                    stack.pop();
+                }
+            }
+        }
+        None
+    }
+
+    /// Kahn's algorithm over indices, dependencies first.
+    fn sorted_indices(&self) -> crate::Result<Vec<usize>> {
+        let mut remaining: Vec<usize> = self.edges.iter().map(Vec::len).collect();
+        let mut ready: BTreeSet<usize> = (0..self.projects.len())
+            .filter(|&i| remaining[i] == 0)
+            .collect();
+
+        let mut order = Vec::with_capacity(self.projects.len());
+        while let Some(i) = ready.pop_first() {
+            order.push(i);
+            for &dependent in &self.reverse[i] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        if order.len() < self.projects.len() {
+            let cycle = self.find_cycle().unwrap_or_default();
+            return Err(crate::Error::DependencyCycle { cycle });
+        }
+        Ok(order)
+    }
+
+    fn neighbours<'a>(&'a self, name: &str, adjacency: &[Vec<usize>]) -> Vec<&'a ProjectInfo> {
+        self.index
+            .get(name)
+            .map(|&i| adjacency[i].iter().map(|&j| &self.projects[j]).collect())
+            .unwrap_or_default()
+    }
+
+    fn closure<'a>(&'a self, name: &str, adjacency: &[Vec<usize>]) -> Vec<&'a ProjectInfo> {
+        let Some(&start) = self.index.get(name) else {
+            return vec![];
+        };
+        let mut seen = vec![false; self.projects.len()];
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            for &j in &adjacency[i] {
+                if !seen[j] {
+                    seen[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+        seen[start] = false;
+        (0..self.projects.len())
+            .filter(|&i| seen[i])
+            .map(|i| &self.projects[i])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, depends_on: &[&str], provides: &[&str]) -> ProjectInfo {
+        ProjectInfo {
+            name: name.to_string(),
+            path: name.to_string(),
+            depends_on: depends_on.iter().map(|s| s.to_string()).collect(),
+            provides: provides.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(projects: Vec<&ProjectInfo>) -> Vec<&str> {
+        projects.into_iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_resolves_names_and_capabilities() {
+        let graph = DependencyGraph::from_projects(vec![
+            project("api", &["db-driver"], &["http-api"]),
+            project("db", &[], &["db-driver"]),
+            project("web", &["http-api", "missing"], &[]),
+        ]);
+
+        assert_eq!(names(graph.dependencies("web")), vec!["api"]);
+        assert_eq!(names(graph.dependencies("api")), vec!["db"]);
+        assert_eq!(names(graph.dependents("db")), vec!["api"]);
+        assert_eq!(
+            graph.unresolved(),
+            &[UnresolvedDependency {
+                project: "web".to_string(),
+                dependency: "missing".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_topological_order_and_closures() {
+        let graph = DependencyGraph::from_projects(vec![
+            project("app", &["lib", "util"], &[]),
+            project("lib", &["util"], &[]),
+            project("tool", &[], &[]),
+            project("util", &[], &[]),
+        ]);
+
+        assert_eq!(
+            names(graph.topological_order().unwrap()),
+            vec!["tool", "util", "lib", "app"]
+        );
+        assert_eq!(
+            names(graph.transitive_dependencies("app")),
+            vec!["lib", "util"]
+        );
+        assert_eq!(
+            names(graph.transitive_dependents("util")),
+            vec!["app", "lib"]
+        );
+        assert!(graph.transitive_dependencies("unknown").is_empty());
+        assert!(graph.find_cycle().is_none());
+    }
+
+    #[test]
+    fn test_cycle_reports_full_path() {
+        let graph = DependencyGraph::from_projects(vec![
+            project("a", &["b"], &[]),
+            project("b", &["c"], &[]),
+            project("c", &["a"], &[]),
+            project("d", &[], &[]),
+        ]);
+
+        assert_eq!(graph.find_cycle().unwrap(), vec!["a", "b", "c", "a"]);
+        match graph.topological_order() {
+            Err(crate::Error::DependencyCycle { cycle }) => {
+                assert_eq!(cycle, vec!["a", "b", "c", "a"]);
+            }
+            other => panic!("expected DependencyCycle, got {other:?}"),
+        }
+    }
+
+    fn level_names(plan: &ExecutionPlan) -> Vec<Vec<&str>> {
+        plan.levels
+            .iter()
+            .map(|level| level.projects.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    fn diamond() -> DependencyGraph {
+        DependencyGraph::from_projects(vec![
+            project("app", &["api", "web"], &[]),
+            project("api", &["core"], &[]),
+            project("core", &[], &[]),
+            project("docs", &[], &[]),
+            project("web", &["core"], &[]),
+        ])
+    }
+
+    #[test]
+    fn test_levels() {
+        let graph = diamond();
+        let levels: Vec<Vec<&str>> = graph.levels().unwrap().into_iter().map(names).collect();
+        assert_eq!(
+            levels,
+            vec![vec!["core", "docs"], vec!["api", "web"], vec!["app"]]
+        );
+    }
+
+    #[test]
+    fn test_execution_plan_parallel_with_limit() {
+        let plan = diamond()
+            .execution_plan(&MetaDefaults::default(), Some(1))
+            .unwrap();
+        // The limit doesn't split levels: `web` needn't wait for `api`
+        assert_eq!(plan.max_concurrency, Some(1));
+        assert_eq!(
+            level_names(&plan),
+            vec![vec!["core", "docs"], vec!["api", "web"], vec!["app"]]
+        );
+
+        let plan = diamond()
+            .execution_plan(&MetaDefaults::default(), None)
+            .unwrap();
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["parallel"], true);
+        assert!(json.get("max_concurrency").is_none());
+        assert_eq!(json["levels"][1]["projects"][0], "api");
+    }
+
+    #[test]
+    fn test_execution_plan_sequential() {
+        let defaults = MetaDefaults {
+            parallel: false,
+            ..Default::default()
+        };
+        let plan = diamond().execution_plan(&defaults, Some(4)).unwrap();
+        assert!(!plan.parallel);
+        assert_eq!(
+            level_names(&plan),
+            vec![
+                vec!["core"],
+                vec!["docs"],
+                vec!["api"],
+                vec!["web"],
+                vec!["app"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_execution_plan_cycle_fails() {
+        let graph = DependencyGraph::from_projects(vec![
+            project("a", &["b"], &[]),
+            project("b", &["a"], &[]),
+        ]);
+        assert!(matches!(
+            graph.execution_plan(&MetaDefaults::default(), None),
+            Err(crate::Error::DependencyCycle { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_tree_includes_nested_children() {
+        let tree = vec![MetaTreeNode {
+            info: project("vendor", &[], &[]),
+            is_meta: true,
+            children: vec![MetaTreeNode {
+                info: project("lib", &["vendor"], &[]),
+                is_meta: false,
+                children: vec![],
+            }],
+        }];
+        let graph = DependencyGraph::from_tree(&tree);
+        assert_eq!(graph.projects().len(), 2);
+        assert_eq!(names(graph.dependencies("lib")), vec!["vendor"]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Matching for the `ignore` list in .meta files.
+//!
+//! Patterns use gitignore-like glob semantics, relative to the directory
+//! containing the .meta file:
+//!
+//! - A pattern without a `/` matches a directory name at any depth
+//!   (`node_modules`, `*.tmp`)
+//! - A pattern with a `/` is anchored to the meta root (`vendor/legacy-*`,
+//!   `/build`); a leading `/` only forces anchoring
+//! - `*` and `?` don't cross `/`; `**` matches any number of directories
+//! - A trailing `/` is accepted and ignored (everything matched is a directory)
+//! - Ignoring a directory ignores everything below it
+//!
+//! A pattern that isn't a valid glob (e.g. an unclosed `[`) matches literally.
+
+/// A compiled set of `ignore` patterns from one .meta file.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreMatcher {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: glob::Pattern,
+    /// Match against the full relative path rather than each component.
+    anchored: bool,
+}
+
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl IgnoreMatcher {
+    /// Compile the patterns from a .meta `ignore` list. Blank entries are skipped.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        let patterns = patterns
+            .iter()
+            .filter_map(|pattern| {
+                let pattern = pattern.as_ref().trim().replace('\\', "/");
+                let pattern = pattern.trim_end_matches('/');
+                let anchored = pattern.contains('/');
+                let pattern = pattern.trim_start_matches('/');
+                if pattern.is_empty() {
+                    return None;
+                }
+                let glob = glob::Pattern::new(pattern)
+                    .or_else(|_| glob::Pattern::new(&glob::Pattern::escape(pattern)))
+                    .ok()?;
+                Some(Pattern { glob, anchored })
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// Whether there are no patterns (nothing is ignored).
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check a `/`-separated path relative to the meta root.
+    ///
+    /// A path is ignored if it, or any directory above it, matches a pattern.
+    pub fn is_ignored(&self, relative_path: &str) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let components: Vec<&str> = relative_path
+            .split(['/', '\\'])
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect();
+
+        (1..=components.len()).any(|len| {
+            let prefix = components[..len].join("/");
+            let name = components[len - 1];
+            self.patterns.iter().any(|pattern| {
+                if pattern.anchored {
+                    pattern.glob.matches_with(&prefix, MATCH_OPTIONS)
+                } else {
+                    pattern.glob.matches_with(name, MATCH_OPTIONS)
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unanchored_patterns_match_any_component() {
+        let matcher = IgnoreMatcher::new(&["node_modules", "*.tmp"]);
+        assert!(matcher.is_ignored("node_modules"));
+        assert!(matcher.is_ignored("web/node_modules"));
+        assert!(matcher.is_ignored("web/node_modules/pkg"));
+        assert!(matcher.is_ignored("scratch.tmp"));
+        assert!(!matcher.is_ignored("web"));
+        assert!(!matcher.is_ignored("node_modules_backup"));
+    }
+
+    #[test]
+    fn test_anchored_patterns_match_from_root() {
+        let matcher = IgnoreMatcher::new(&["vendor/legacy-*", "/build/", "archive/**/old"]);
+        assert!(matcher.is_ignored("vendor/legacy-api"));
+        assert!(matcher.is_ignored("vendor/legacy-api/nested"));
+        assert!(!matcher.is_ignored("other/vendor/legacy-api"));
+        // `*` doesn't cross directory separators
+        assert!(!matcher.is_ignored("vendor/current/legacy-x"));
+        assert!(matcher.is_ignored("build"));
+        assert!(!matcher.is_ignored("web/build"));
+        assert!(matcher.is_ignored("archive/old"));
+        assert!(matcher.is_ignored("archive/2019/q1/old"));
+    }
+
+    #[test]
+    fn test_invalid_and_blank_patterns() {
+        let matcher = IgnoreMatcher::new(&["", "  ", "[draft"]);
+        assert!(matcher.is_ignored("[draft"));
+        assert!(!matcher.is_ignored("d"));
+        assert!(IgnoreMatcher::new::<&str>(&[]).is_empty());
+    }
+}
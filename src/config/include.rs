@@ -0,0 +1,344 @@
+//! Resolution of `include` (alias `extends`) in .meta files.
+//!
+//! A .meta file can pull projects, ignore patterns and defaults from other
+//! local fragment files:
+//!
+//! ```yaml
+//! include:
+//!   - ../shared/platform.meta.yaml
+//! projects:
+//!   app: git@github.com:org/app.git
+//! ```
+//!
+//! Merge rules, applied in order (each include, then the file itself):
+//!
+//! - `projects` merge by name; a later definition replaces an earlier one
+//!   entirely, so local entries override included ones
+//! - `ignore` patterns are concatenated, dropping duplicates
+//! - `defaults` merge key by key; only keys a file sets override
+//! - `url_rewrites` merge by prefix
+//! - `profiles` merge by name; a later definition replaces an earlier one
+//! - `worktrees_dir` and `github_shorthand` are never inherited from fragments
+//!
+//! Project paths and ignore patterns in fragments stay relative to the meta
+//! root that ends up using them, not to the fragment file.
+//!
+//! Include paths are relative to the including file. Fragments can include
+//! other fragments; a file including itself (directly or indirectly) is an
+//! `Error::IncludeCycle`. Fragment files use the same format detection as
+//! .meta files (`.yaml`/`.yml` is YAML, anything else JSON).
+
+use super::defaults::{self, DefaultsOrigin, ResolvedDefaults, Setting};
+use super::{load_source, ConfigFormat, MetaConfig, MetaDefaults, Profile, ProjectEntry};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// A config with its includes merged in.
+pub(super) struct Resolved {
+    pub config: MetaConfig,
+    /// The file each project's effective definition came from.
+    pub sources: HashMap<String, PathBuf>,
+    /// Every file that was loaded, root first, with its source for diagnostics.
+    pub files: Vec<LoadedFile>,
+    /// What the user's overlay changed, if one was applied.
+    pub overlay: Option<super::OverlayReport>,
+    /// `defaults` settings from the files (and overlay), lowest precedence first.
+    pub default_settings: Vec<Setting>,
+    /// `config.defaults` with the origin of each setting.
+    pub defaults: ResolvedDefaults,
+}
+
+impl Resolved {
+    /// Re-resolve `config.defaults` with extra layers below (`before`) and
+    /// above (`after`) the file settings.
+    pub fn resolve_defaults(&mut self, before: Vec<Setting>, after: Vec<Setting>) {
+        let settings = before
+            .into_iter()
+            .chain(self.default_settings.iter().cloned())
+            .chain(after);
+        self.defaults = defaults::resolve(settings);
+        self.config.defaults = self.defaults.defaults.clone();
+    }
+}
+
+/// One parsed file of an include tree.
+pub(super) struct LoadedFile {
+    pub path: PathBuf,
+    pub source: String,
+    pub format: ConfigFormat,
+    pub config: MetaConfig,
+}
+
+/// Load `meta_path` and merge in everything it includes.
+pub(super) fn resolve(meta_path: &Path) -> crate::Result<Resolved> {
+    let mut files = Vec::new();
+    let mut stack = Vec::new();
+    let layer = load(meta_path, &mut stack, &mut files)?;
+
+    // The root file was pushed first
+    let root = &files[0].config;
+    let config = MetaConfig {
+        projects: layer
+            .projects
+            .iter()
+            .map(|(name, (entry, _))| (name.clone(), entry.clone()))
+            .collect(),
+        ignore: layer.ignore,
+        defaults: MetaDefaults::default(),
+        worktrees_dir: root.worktrees_dir.clone(),
+        include: root.include.clone(),
+        url_rewrites: layer.url_rewrites,
+        github_shorthand: root.github_shorthand,
+        profiles: layer.profiles,
+    };
+    let sources = layer
+        .projects
+        .into_iter()
+        .map(|(name, (_, source))| (name, source))
+        .collect();
+
+    let default_settings = layer
+        .defaults
+        .into_iter()
+        .map(|(key, (value, source))| (key, value, DefaultsOrigin::Repo(source)))
+        .collect();
+
+    let mut resolved = Resolved {
+        config,
+        sources,
+        files,
+        overlay: None,
+        default_settings,
+        defaults: defaults::resolve([]),
+    };
+    resolved.resolve_defaults(vec![], vec![]);
+    Ok(resolved)
+}
+
+/// The merged contributions of one file and its includes.
+#[derive(Default)]
+struct Layer {
+    projects: HashMap<String, (ProjectEntry, PathBuf)>,
+    ignore: Vec<String>,
+    /// Only the `defaults` keys that some file actually set, with that file.
+    defaults: BTreeMap<String, (Value, PathBuf)>,
+    url_rewrites: BTreeMap<String, String>,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Layer {
+    fn merge(&mut self, other: Layer) {
+        self.projects.extend(other.projects);
+        for pattern in other.ignore {
+            if !self.ignore.contains(&pattern) {
+                self.ignore.push(pattern);
+            }
+        }
+        self.defaults.extend(other.defaults);
+        self.url_rewrites.extend(other.url_rewrites);
+        self.profiles.extend(other.profiles);
+    }
+}
+
+fn load(
+    meta_path: &Path,
+    stack: &mut Vec<PathBuf>,
+    files: &mut Vec<LoadedFile>,
+) -> crate::Result<Layer> {
+    let canonical = meta_path
+        .canonicalize()
+        .map_err(|e| crate::Error::io(meta_path, e))?;
+    if let Some(start) = stack.iter().position(|p| *p == canonical) {
+        let mut chain = stack[start..].to_vec();
+        chain.push(canonical);
+        return Err(crate::Error::IncludeCycle { chain });
+    }
+
+    let (source, format, config) = load_source(meta_path)?;
+    let defaults = explicit_defaults(&source, format);
+    let base_dir = meta_path.parent().unwrap_or(Path::new("."));
+    let includes: Vec<PathBuf> = config.include.iter().map(|i| base_dir.join(i)).collect();
+
+    let own = Layer {
+        projects: config
+            .projects
+            .iter()
+            .map(|(name, entry)| (name.clone(), (entry.clone(), meta_path.to_path_buf())))
+            .collect(),
+        ignore: config.ignore.clone(),
+        defaults: defaults
+            .into_iter()
+            .map(|(key, value)| (key, (value, meta_path.to_path_buf())))
+            .collect(),
+        url_rewrites: config.url_rewrites.clone(),
+        profiles: config.profiles.clone(),
+    };
+    files.push(LoadedFile {
+        path: meta_path.to_path_buf(),
+        source,
+        format,
+        config,
+    });
+
+    stack.push(canonical);
+    let mut layer = Layer::default();
+    for include in includes {
+        layer.merge(load(&include, stack, files)?);
+    }
+    stack.pop();
+
+    layer.merge(own);
+    Ok(layer)
+}
+
+/// The `defaults` keys set in a file's source.
+///
+/// `MetaDefaults` fills in missing keys, so the typed value can't tell an
+/// explicit `parallel: true` from an absent one.
+fn explicit_defaults(source: &str, format: ConfigFormat) -> Map<String, Value> {
+    let value: Option<Value> = match format {
+        ConfigFormat::Json => serde_json::from_str(source).ok(),
+        ConfigFormat::Yaml => serde_yaml_ng::from_str(source).ok(),
+    };
+    match value {
+        Some(Value::Object(mut root)) => match root.remove("defaults") {
+            Some(Value::Object(defaults)) => defaults,
+            _ => Map::new(),
+        },
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::tests::{isolated, load_defaults};
+    use crate::config::{parse_meta_config_with, ParseOptions};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_includes_merge_with_local_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join("shared");
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(
+            shared.join("platform.meta.yaml"),
+            "include:\n  - base.meta\nprojects:\n  auth: git@github.com:org/auth.git\n  api: git@github.com:org/api-v1.git\nignore:\n  - tmp\n  - build\n",
+        )
+        .unwrap();
+        std::fs::write(
+            shared.join("base.meta"),
+            r#"{"projects": {"logging": "git@github.com:org/logging.git"}, "defaults": {"parallel": false}}"#,
+        )
+        .unwrap();
+        let root_meta = dir.path().join(".meta");
+        std::fs::write(
+            &root_meta,
+            r#"{
+                "extends": ["shared/platform.meta.yaml"],
+                "projects": {"api": "git@github.com:org/api.git"},
+                "ignore": ["tmp", "dist"]
+            }"#,
+        )
+        .unwrap();
+
+        let (projects, ignore) = parse_meta_config_with(&root_meta, &isolated(dir.path())).unwrap();
+        let summary: Vec<(&str, &str, PathBuf)> = projects
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_str(),
+                    p.repo.as_deref().unwrap(),
+                    p.source.clone().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("api", "git@github.com:org/api.git", root_meta.clone()),
+                (
+                    "auth",
+                    "git@github.com:org/auth.git",
+                    shared.join("platform.meta.yaml")
+                ),
+                (
+                    "logging",
+                    "git@github.com:org/logging.git",
+                    shared.join("base.meta")
+                ),
+            ]
+        );
+        assert_eq!(ignore, vec!["tmp", "build", "dist"]);
+        // `parallel: false` from the fragment isn't overridden by the local
+        // file, which doesn't set it
+        assert!(!load_defaults(dir.path()).parallel);
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".meta"), r#"{"include": ["a.meta"]}"#).unwrap();
+        std::fs::write(dir.path().join("a.meta"), r#"{"include": ["b.meta"]}"#).unwrap();
+        std::fs::write(dir.path().join("b.meta"), r#"{"include": ["a.meta"]}"#).unwrap();
+
+        match parse_meta_config_with(&dir.path().join(".meta"), &isolated(dir.path())) {
+            Err(crate::Error::IncludeCycle { chain }) => {
+                let names: Vec<String> = chain
+                    .iter()
+                    .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+                    .collect();
+                assert_eq!(names, vec!["a.meta", "b.meta", "a.meta"]);
+            }
+            other => panic!("expected IncludeCycle, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_missing_include_is_io_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".meta"), r#"{"include": ["missing.meta"]}"#).unwrap();
+        match parse_meta_config_with(&dir.path().join(".meta"), &isolated(dir.path())) {
+            Err(crate::Error::Io { path, .. }) => assert!(path.ends_with("missing.meta")),
+            other => panic!("expected Io, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_checks_fragments() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_meta = dir.path().join(".meta");
+        std::fs::write(
+            &root_meta,
+            r#"{"include": ["shared.meta"], "projects": {"app": {"repo": "git@github.com:org/app.git", "depends_on": ["auth"]}}}"#,
+        )
+        .unwrap();
+        let strict = ParseOptions {
+            strict: true,
+            ..isolated(dir.path())
+        };
+
+        // Dependencies resolve against included projects
+        std::fs::write(
+            dir.path().join("shared.meta"),
+            r#"{"projects": {"auth": "git@github.com:org/auth.git"}}"#,
+        )
+        .unwrap();
+        assert!(parse_meta_config_with(&root_meta, &strict).is_ok());
+
+        // Problems in a fragment are reported against the fragment
+        std::fs::write(
+            dir.path().join("shared.meta"),
+            r#"{"projects": {"auth": {"repo": "git@github.com:org/auth.git", "tag": ["x"]}}}"#,
+        )
+        .unwrap();
+        match parse_meta_config_with(&root_meta, &strict) {
+            Err(crate::Error::ConfigInvalid { path, diagnostics }) => {
+                assert!(path.ends_with("shared.meta"));
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].line, Some(1));
+            }
+            other => panic!("expected ConfigInvalid, got {other:?}"),
+        }
+    }
+}
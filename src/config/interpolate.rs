@@ -0,0 +1,208 @@
+//! Environment-variable interpolation in .meta values.
+//!
+//! `repo`, `path` and `worktrees_dir` values may reference environment
+//! variables, so one .meta file can serve CI and laptops:
+//!
+//! ```yaml
+//! projects:
+//!   api: ${GIT_BASE:-git@github.com:org}/api.git
+//!   tools:
+//!     repo: https://${CI_TOKEN}@github.com/org/tools.git
+//! ```
+//!
+//! - `${VAR}` is replaced with the value of `VAR`; it's an error if unset
+//! - `${VAR:-default}` uses `default` when `VAR` is unset or empty
+//! - `$${` produces a literal `${`
+//!
+//! Other `$` characters are left alone.
+
+use super::include::Resolved;
+use super::ProjectEntry;
+
+/// Interpolate `repo`, `path` and `worktrees_dir` in a resolved config.
+///
+/// Fails with `Error::ConfigInterpolation` naming the file, project and field
+/// of the first value that references an unset variable or is malformed.
+pub(super) fn apply(
+    resolved: &mut Resolved,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> crate::Result<()> {
+    let root = resolved.files[0].path.clone();
+
+    let mut names: Vec<String> = resolved.config.projects.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let file = resolved.sources.get(&name).unwrap_or(&root).clone();
+        let error = |field: &str, message: String| crate::Error::ConfigInterpolation {
+            path: file.clone(),
+            project: Some(name.clone()),
+            field: field.to_string(),
+            message,
+        };
+        match resolved.config.projects.get_mut(&name) {
+            Some(ProjectEntry::Simple(url)) => {
+                *url = interpolate(url, &lookup).map_err(|m| error("repo", m))?;
+            }
+            Some(ProjectEntry::Extended { repo, path, .. }) => {
+                if let Some(repo) = repo {
+                    *repo = interpolate(repo, &lookup).map_err(|m| error("repo", m))?;
+                }
+                if let Some(path) = path {
+                    *path = interpolate(path, &lookup).map_err(|m| error("path", m))?;
+                }
+            }
+            None => {}
+        }
+    }
+
+    if let Some(worktrees_dir) = &mut resolved.config.worktrees_dir {
+        *worktrees_dir = interpolate(worktrees_dir, &lookup).map_err(|message| {
+            crate::Error::ConfigInterpolation {
+                path: root.clone(),
+                project: None,
+                field: "worktrees_dir".to_string(),
+                message,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` in `value`.
+///
+/// Returns a message describing the problem if a variable is unset or a
+/// reference is malformed.
+pub(super) fn interpolate(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+
+        if let Some(escaped) = after.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(reference) = after.strip_prefix("${") else {
+            out.push('$');
+            rest = &after[1..];
+            continue;
+        };
+        let Some(end) = reference.find('}') else {
+            return Err(format!("unclosed `${{` in `{value}`"));
+        };
+
+        let body = &reference[..end];
+        let (name, default) = match body.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (body, None),
+        };
+        if !is_valid_name(name) {
+            return Err(format!("invalid variable name `{name}` in `{value}`"));
+        }
+
+        match (lookup(name), default) {
+            (Some(found), Some(default)) if found.is_empty() => out.push_str(default),
+            (Some(found), _) => out.push_str(&found),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => {
+                return Err(format!(
+                    "environment variable `{name}` is not set (use `${{{name}:-default}}` to provide a fallback)"
+                ))
+            }
+        }
+        rest = &reference[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "GIT_BASE" => Some("https://github.com/org".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let cases = [
+            ("${GIT_BASE}/api.git", "https://github.com/org/api.git"),
+            (
+                "${MISSING:-git@github.com:org}/api.git",
+                "git@github.com:org/api.git",
+            ),
+            ("${EMPTY:-fallback}", "fallback"),
+            ("x${EMPTY}y", "xy"),
+            ("${GIT_BASE:-unused}", "https://github.com/org"),
+            ("cost$5 and $${LITERAL}", "cost$5 and ${LITERAL}"),
+            ("plain", "plain"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(interpolate(input, lookup).unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_interpolate_errors() {
+        let err = interpolate("${TOKEN}@host", lookup).unwrap_err();
+        assert!(err.contains("`TOKEN` is not set"), "{err}");
+        assert!(interpolate("${GIT_BASE", lookup)
+            .unwrap_err()
+            .contains("unclosed"));
+        assert!(interpolate("${1X}", lookup)
+            .unwrap_err()
+            .contains("invalid variable name `1X`"));
+    }
+
+    #[test]
+    fn test_parse_meta_config_interpolates() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = crate::config::tests::isolated(dir.path());
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  api: ${META_CORE_TEST_BASE:-git@github.com:org}/api.git\n  web:\n    repo: git@github.com:org/web.git\n    path: ${META_CORE_TEST_UNSET_DIR:-apps}/web\n",
+        )
+        .unwrap();
+        let (projects, _) = crate::config::parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("git@github.com:org/api.git")
+        );
+        assert_eq!(projects[1].path, "apps/web");
+
+        std::fs::write(
+            &meta,
+            "projects:\n  api: ${META_CORE_TEST_UNSET_TOKEN}/api.git\n",
+        )
+        .unwrap();
+        match crate::config::parse_meta_config_with(&meta, &options) {
+            Err(err @ crate::Error::ConfigInterpolation { .. }) => {
+                let text = err.to_string();
+                assert!(text.contains("`repo` of project `api`"), "{text}");
+                assert!(text.contains("META_CORE_TEST_UNSET_TOKEN"), "{text}");
+            }
+            other => panic!("expected ConfigInterpolation, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Per-user overlays on top of a shared .meta file.
+//!
+//! An overlay lets one engineer adjust a meta repo locally without touching
+//! the committed .meta file. It lives in the meta data directory, keyed by
+//! the meta root (see `overlay_path`):
+//!
+//! ```yaml
+//! projects:
+//!   api:
+//!     path: ../api-worktree   # replaces the project's path
+//!     tags: [mine]            # added to the project's tags
+//! defaults:
+//!   parallel: false
+//! ignore:
+//!   - scratch                 # added to the ignore list
+//! ```
+//!
+//! Overlays are applied after `include`s are merged and before `${VAR}`
+//! interpolation, so overlay paths may reference variables too. Like paths
+//! in the .meta file, they may point outside the meta root (e.g. at a
+//! worktree next to it); strict parsing only checks paths in .meta files. Entries for
+//! projects the config doesn't define are skipped and listed in the report.
+
+use super::defaults::{self, DefaultsOrigin, PartialDefaults};
+use super::include::Resolved;
+use super::{diagnostics, ProjectEntry};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Subdirectory of the meta data directory holding overlay files.
+const OVERLAY_DIR: &str = "overrides";
+
+/// Local adjustments to one meta repo's config.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Overlay {
+    /// Per-project adjustments, by project name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub projects: BTreeMap<String, ProjectOverlay>,
+    /// Defaults to override.
+    #[serde(default)]
+    pub defaults: PartialDefaults,
+    /// Extra ignore patterns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+/// Local adjustments to one project.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectOverlay {
+    /// Replacement path, relative to the meta root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Extra tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// A value in the effective config that came from an overlay.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverlayValue {
+    /// Dotted key of the value, e.g. `projects.api.path` or `defaults.parallel`.
+    pub key: String,
+    /// The overlay's contribution (for lists, only the added items).
+    pub value: Value,
+}
+
+/// What an overlay changed in a config.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverlayReport {
+    /// The overlay file.
+    pub path: PathBuf,
+    /// Values taken from the overlay, in key order.
+    pub values: Vec<OverlayValue>,
+    /// Overlay project entries that match no project in the config.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unmatched_projects: Vec<String>,
+}
+
+impl OverlayReport {
+    /// Whether `key` (e.g. `projects.api.tags`) came from the overlay.
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.iter().any(|v| v.key == key)
+    }
+}
+
+/// Path of the overlay file for the meta repo rooted at `meta_root`.
+///
+/// The file name is a hash of the canonicalized root, so every checkout of a
+/// meta repo gets its own overlay: `~/.meta/overrides/<hash>.yaml`.
+pub fn overlay_path(meta_root: &Path) -> PathBuf {
+    overlay_path_in(&crate::meta_dir(), meta_root)
+}
+
+/// Like `overlay_path`, in the meta data directory `meta_dir`.
+pub(super) fn overlay_path_in(meta_dir: &Path, meta_root: &Path) -> PathBuf {
+    let root = meta_root
+        .canonicalize()
+        .unwrap_or_else(|_| meta_root.to_path_buf());
+    meta_dir.join(OVERLAY_DIR).join(format!(
+        "{:016x}.yaml",
+        fnv1a(root.to_string_lossy().as_bytes())
+    ))
+}
+
+/// FNV-1a, which (unlike `DefaultHasher`) is stable across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Load an overlay file, or `None` if it doesn't exist.
+pub(super) fn load(path: &Path) -> crate::Result<Option<Overlay>> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(crate::Error::io(path, e)),
+    };
+    if source.trim().is_empty() {
+        return Ok(Some(Overlay::default()));
+    }
+    serde_yaml_ng::from_str(&source)
+        .map(Some)
+        .map_err(|e| diagnostics::yaml_error(path, &source, &e))
+}
+
+/// Merge `overlay` into a resolved config and record what it changed.
+pub(super) fn apply(resolved: &mut Resolved, overlay: Overlay, path: &Path) -> OverlayReport {
+    let config = &mut resolved.config;
+    let mut values = Vec::new();
+    let mut unmatched_projects = Vec::new();
+
+    for (name, project) in overlay.projects {
+        let Some(entry) = config.projects.get_mut(&name) else {
+            unmatched_projects.push(name);
+            continue;
+        };
+        if let ProjectEntry::Simple(url) = entry {
+            *entry = ProjectEntry::Extended {
+                repo: Some(std::mem::take(url)),
+                path: None,
+                tags: vec![],
+                provides: vec![],
+                depends_on: vec![],
+                meta: false,
+            };
+        }
+        let ProjectEntry::Extended { path, tags, .. } = entry else {
+            continue;
+        };
+
+        if let Some(overlay_path) = project.path {
+            values.push(OverlayValue {
+                key: format!("projects.{name}.path"),
+                value: Value::String(overlay_path.clone()),
+            });
+            *path = Some(overlay_path);
+        }
+        let added: Vec<String> = project
+            .tags
+            .into_iter()
+            .filter(|tag| !tags.contains(tag))
+            .collect();
+        if !added.is_empty() {
+            tags.extend(added.iter().cloned());
+            values.push(OverlayValue {
+                key: format!("projects.{name}.tags"),
+                value: Value::from(added),
+            });
+        }
+    }
+
+    for (key, value) in overlay.defaults.values() {
+        values.push(OverlayValue {
+            key: format!("defaults.{key}"),
+            value,
+        });
+    }
+
+    let added: Vec<String> = overlay
+        .ignore
+        .into_iter()
+        .filter(|pattern| !config.ignore.contains(pattern))
+        .collect();
+    if !added.is_empty() {
+        config.ignore.extend(added.iter().cloned());
+        values.push(OverlayValue {
+            key: "ignore".to_string(),
+            value: Value::from(added),
+        });
+    }
+
+    resolved.default_settings.extend(defaults::settings(
+        &overlay.defaults,
+        DefaultsOrigin::Overlay(path.to_path_buf()),
+    ));
+    resolved.resolve_defaults(vec![], vec![]);
+
+    values.sort_by(|a, b| a.key.cmp(&b.key));
+    OverlayReport {
+        path: path.to_path_buf(),
+        values,
+        unmatched_projects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::include;
+
+    #[test]
+    fn test_overlay_path_is_stable_per_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta_dir = dir.path().join("meta-data");
+        let a = overlay_path_in(&meta_dir, dir.path());
+        assert_eq!(a, overlay_path_in(&meta_dir, dir.path()));
+        assert_ne!(a, overlay_path_in(&meta_dir, &dir.path().join("other")));
+        assert_eq!(a, overlay_path_in(&meta_dir, &dir.path().join(".")));
+        assert!(a.starts_with(&meta_dir));
+        assert!(a.ends_with(format!(
+            "overrides/{:016x}.yaml",
+            fnv1a(
+                dir.path()
+                    .canonicalize()
+                    .unwrap()
+                    .to_string_lossy()
+                    .as_bytes()
+            )
+        )));
+        // Known FNV-1a 64 test vector
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_apply_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  api: git@github.com:org/api.git\n  web:\n    repo: git@github.com:org/web.git\n    tags: [frontend]\nignore: [tmp]\n",
+        )
+        .unwrap();
+        let overlay_file = dir.path().join("overlay.yaml");
+        std::fs::write(
+            &overlay_file,
+            "projects:\n  api:\n    path: ../api-dev\n  web:\n    tags: [frontend, mine]\n  gone:\n    tags: [x]\ndefaults:\n  parallel: false\nignore: [tmp, scratch]\n",
+        )
+        .unwrap();
+
+        let mut resolved = include::resolve(&meta).unwrap();
+        let overlay = load(&overlay_file).unwrap().unwrap();
+        let report = apply(&mut resolved, overlay, &overlay_file);
+
+        let config = &resolved.config;
+        assert!(matches!(
+            &config.projects["api"],
+            ProjectEntry::Extended { repo: Some(repo), path: Some(path), .. }
+                if repo == "git@github.com:org/api.git" && path == "../api-dev"
+        ));
+        assert!(matches!(
+            &config.projects["web"],
+            ProjectEntry::Extended { tags, .. } if *tags == ["frontend", "mine"]
+        ));
+        assert!(!config.defaults.parallel);
+        assert_eq!(config.ignore, vec!["tmp", "scratch"]);
+
+        let keys: Vec<&str> = report.values.iter().map(|v| v.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "defaults.parallel",
+                "ignore",
+                "projects.api.path",
+                "projects.web.tags"
+            ]
+        );
+        assert_eq!(report.values[3].value, serde_json::json!(["mine"]));
+        assert!(report.contains("projects.api.path"));
+        assert_eq!(report.unmatched_projects, vec!["gone"]);
+    }
+
+    #[test]
+    fn test_missing_and_invalid_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overlay.yaml");
+        assert_eq!(load(&path).unwrap(), None);
+
+        std::fs::write(&path, "projects:\n  api:\n    repo: x\n").unwrap();
+        assert!(matches!(
+            load(&path),
+            Err(crate::Error::ConfigParse { line: Some(3), .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_applies_overlay_from_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta");
+        std::fs::write(
+            &meta,
+            r#"{"projects": {"api": "git@github.com:org/api.git"}}"#,
+        )
+        .unwrap();
+        let options = crate::config::tests::isolated(dir.path());
+        assert_eq!(
+            crate::config::overlay_report_with(&meta, &options).unwrap(),
+            None
+        );
+
+        let overlay_file = overlay_path_in(&options.sources.meta_dir(), dir.path());
+        std::fs::create_dir_all(overlay_file.parent().unwrap()).unwrap();
+        std::fs::write(&overlay_file, "projects:\n  api:\n    tags: [mine]\n").unwrap();
+        let (projects, _) = crate::config::parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(projects[0].tags, vec!["mine"]);
+        let report = crate::config::overlay_report_with(&meta, &options)
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.path, overlay_file);
+        assert!(report.contains("projects.api.tags"));
+    }
+}
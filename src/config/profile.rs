@@ -0,0 +1,255 @@
+//! Named profiles in .meta files.
+//!
+//! A profile adjusts the config for one environment:
+//!
+//! ```yaml
+//! profiles:
+//!   ci:
+//!     filter: "!local-only"
+//!     defaults:
+//!       fail_fast: true
+//!     url_rewrites:
+//!       "git@github.com:": "https://github.com/"
+//!   minimal:
+//!     filter: core || api
+//! ```
+//!
+//! - `filter` keeps only the projects matching a `ProjectQuery`
+//! - `defaults` override the file's defaults (but not overlays or `META_*`)
+//! - `url_rewrites` are added to the file's rules, replacing rules with the same prefix
+//!
+//! A profile is selected with `ParseOptions::profile`, or the `META_PROFILE`
+//! environment variable by default. Files that define no profiles ignore the
+//! selection, so a global `META_PROFILE` doesn't break unrelated meta repos.
+
+use super::defaults::{self, DefaultsOrigin, PartialDefaults};
+use super::include::Resolved;
+use super::{ConfigSources, ProjectInfo, ProjectQuery};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Environment variable selecting a profile by default.
+const META_PROFILE_ENV: &str = "META_PROFILE";
+
+/// A named set of adjustments in a .meta file's `profiles` section.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Keep only the projects matching this query (e.g. `frontend && !legacy`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Defaults to override.
+    #[serde(default)]
+    pub defaults: PartialDefaults,
+    /// Repo URL prefix rewrites added to the file's `url_rewrites`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub url_rewrites: BTreeMap<String, String>,
+}
+
+/// Which profile to apply when loading a config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ProfileSelection {
+    /// The profile named by `META_PROFILE`, if set.
+    #[default]
+    Env,
+    /// The named profile.
+    Named(String),
+    /// No profile, even if `META_PROFILE` is set.
+    Disabled,
+}
+
+impl ProfileSelection {
+    /// The requested profile name, if any.
+    fn name(&self, sources: &ConfigSources) -> Option<String> {
+        match self {
+            ProfileSelection::Env => sources
+                .var(META_PROFILE_ENV)
+                .filter(|name| !name.trim().is_empty()),
+            ProfileSelection::Named(name) => Some(name.clone()),
+            ProfileSelection::Disabled => None,
+        }
+    }
+}
+
+/// Look up the selected profile in a resolved config.
+///
+/// Fails with `Error::UnknownProfile` if the config defines profiles but not
+/// the selected one.
+pub(super) fn select(
+    resolved: &Resolved,
+    selection: &ProfileSelection,
+    sources: &ConfigSources,
+) -> crate::Result<Option<(String, Profile)>> {
+    let Some(name) = selection.name(sources) else {
+        return Ok(None);
+    };
+    let profiles = &resolved.config.profiles;
+    if profiles.is_empty() {
+        return Ok(None);
+    }
+    match profiles.get(&name) {
+        Some(profile) => Ok(Some((name, profile.clone()))),
+        None => Err(crate::Error::UnknownProfile {
+            name,
+            path: resolved.files[0].path.clone(),
+            available: profiles.keys().cloned().collect(),
+        }),
+    }
+}
+
+/// Apply a profile's defaults and URL rewrites.
+///
+/// Call before the overlay is applied, so overlay defaults take precedence.
+pub(super) fn apply_settings(resolved: &mut Resolved, name: &str, profile: &Profile) {
+    resolved
+        .config
+        .url_rewrites
+        .extend(profile.url_rewrites.clone());
+    resolved.default_settings.extend(defaults::settings(
+        &profile.defaults,
+        DefaultsOrigin::Profile(name.to_string()),
+    ));
+    resolved.resolve_defaults(vec![], vec![]);
+}
+
+/// Drop the projects that don't match a profile's `filter`.
+///
+/// Call after interpolation, so `path:` atoms see the final paths.
+pub(super) fn apply_filter(resolved: &mut Resolved, profile: &Profile) -> crate::Result<()> {
+    let Some(filter) = &profile.filter else {
+        return Ok(());
+    };
+    let query = ProjectQuery::parse(filter)?;
+    resolved.config.projects.retain(|name, entry| {
+        query.matches(&ProjectInfo::from_entry(name.clone(), entry.clone(), None))
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::isolated;
+    use crate::config::{
+        load_meta_config_with, parse_meta_config_with, walk_meta_tree_with, ParseOptions,
+    };
+    use std::path::Path;
+
+    const META: &str = r#"
+projects:
+  core:
+    repo: git@github.com:org/core.git
+    tags: [core]
+  web:
+    repo: git@github.com:org/web.git
+    tags: [frontend]
+  docs:
+    repo: git@github.com:org/docs.git
+    tags: [docs]
+defaults:
+  max_concurrency: 8
+profiles:
+  ci:
+    filter: "!docs"
+    defaults:
+      fail_fast: true
+      max_concurrency: 2
+    url_rewrites:
+      "git@github.com:": "https://github.com/"
+  minimal:
+    filter: core
+"#;
+
+    fn options(dir: &Path, profile: ProfileSelection) -> ParseOptions {
+        ParseOptions {
+            profile,
+            ..isolated(dir)
+        }
+    }
+
+    #[test]
+    fn test_profile_filters_and_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(&meta, META).unwrap();
+
+        let ci = options(dir.path(), ProfileSelection::Named("ci".to_string()));
+        let (projects, _) = parse_meta_config_with(&meta, &ci).unwrap();
+        let names: Vec<&str> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["core", "web"]);
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("https://github.com/org/core.git")
+        );
+
+        let config = load_meta_config_with(&meta, &ci).unwrap();
+        assert!(config.defaults.fail_fast);
+        assert_eq!(config.defaults.max_concurrency, Some(2));
+
+        let full = options(dir.path(), ProfileSelection::Disabled);
+        let (projects, _) = parse_meta_config_with(&meta, &full).unwrap();
+        assert_eq!(projects.len(), 3);
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("git@github.com:org/core.git")
+        );
+
+        let tree = walk_meta_tree_with(
+            dir.path(),
+            None,
+            &options(dir.path(), ProfileSelection::Named("minimal".to_string())),
+        )
+        .unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].info.name, "core");
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(&meta, META).unwrap();
+
+        match parse_meta_config_with(
+            &meta,
+            &options(dir.path(), ProfileSelection::Named("full".into())),
+        ) {
+            Err(crate::Error::UnknownProfile {
+                name, available, ..
+            }) => {
+                assert_eq!(name, "full");
+                assert_eq!(available, vec!["ci", "minimal"]);
+            }
+            other => panic!("expected UnknownProfile, got {other:?}"),
+        }
+
+        // Files without profiles ignore the selection
+        std::fs::write(&meta, "projects:\n  core: git@github.com:org/core.git\n").unwrap();
+        let (projects, _) = parse_meta_config_with(
+            &meta,
+            &options(dir.path(), ProfileSelection::Named("full".into())),
+        )
+        .unwrap();
+        assert_eq!(projects.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_validates_before_filtering() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  core:\n    repo: git@github.com:org/core.git\n    tags: [core]\n  web:\n    repo: git@github.com:org/web.git\n    depends_on: [core]\nprofiles:\n  no-core:\n    filter: \"!core\"\n",
+        )
+        .unwrap();
+
+        let strict = ParseOptions {
+            strict: true,
+            ..options(dir.path(), ProfileSelection::Named("no-core".to_string()))
+        };
+        let (projects, _) = parse_meta_config_with(&meta, &strict).unwrap();
+        let names: Vec<&str> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["web"]);
+    }
+}
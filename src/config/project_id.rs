@@ -0,0 +1,391 @@
+//! Identifiers for projects across a nested meta tree.
+//!
+//! A project name is only unique within its own .meta file: two nested meta
+//! repos can both define `api`. A `ProjectId` is the chain of names from the
+//! root .meta down to the project (`platform/api`), which is unique across
+//! the whole tree. `ProjectIndex` maps ids to projects and resolves user
+//! input to one project.
+
+use super::{MetaTreeNode, ProjectInfo, ProjectPath};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Separator accepted (and displayed) when a name itself contains `/`.
+const NAME_SEPARATOR: &str = "::";
+
+/// A project's chain of names from the root of the meta tree.
+///
+/// Displayed as `platform/api`, or `platform::api` if a name contains `/`
+/// (e.g. projects imported from `.gitmodules`). A top-level project whose
+/// name contains `/` is displayed with a leading `::` (`::libs/api`), so it
+/// doesn't read as a nested id. All forms parse, and display followed by
+/// `parse` gives back the same id as long as no name is empty, contains
+/// `::` or has surrounding whitespace (`ProjectIndex` rejects such names).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProjectId {
+    names: Vec<String>,
+}
+
+impl ProjectId {
+    /// The id of a top-level project.
+    pub fn root(name: impl Into<String>) -> Self {
+        ProjectId {
+            names: vec![name.into()],
+        }
+    }
+
+    /// The id of project `name` in the nested meta repo this id refers to.
+    pub fn child(&self, name: impl Into<String>) -> Self {
+        let mut names = self.names.clone();
+        names.push(name.into());
+        ProjectId { names }
+    }
+
+    /// Parse `platform/api`, `platform::api` or `::libs/api`.
+    ///
+    /// Fails with `Error::InvalidProjectId` if the input or any name is empty.
+    pub fn parse(input: &str) -> crate::Result<Self> {
+        let trimmed = input.trim();
+        // A leading `::` marks the `::` form even for a single name
+        let (trimmed, marked) = match trimmed.strip_prefix(NAME_SEPARATOR) {
+            Some(rest) => (rest, true),
+            None => (trimmed, false),
+        };
+        let separator = if marked || trimmed.contains(NAME_SEPARATOR) {
+            NAME_SEPARATOR
+        } else {
+            "/"
+        };
+        let names: Vec<String> = trimmed
+            .split(separator)
+            .map(|name| name.trim().to_string())
+            .collect();
+        if names.iter().any(String::is_empty) {
+            return Err(crate::Error::InvalidProjectId {
+                input: input.to_string(),
+                message: "project names must not be empty".to_string(),
+            });
+        }
+        Ok(ProjectId { names })
+    }
+
+    /// Names from the root down to the project.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The project's own name, as written in its .meta file.
+    pub fn name(&self) -> &str {
+        self.names.last().map(String::as_str).unwrap_or_default()
+    }
+
+    /// The id of the nested meta repo defining this project, if any.
+    pub fn parent(&self) -> Option<ProjectId> {
+        (self.names.len() > 1).then(|| ProjectId {
+            names: self.names[..self.names.len() - 1].to_vec(),
+        })
+    }
+
+    /// Nesting depth; top-level projects are at depth 0.
+    pub fn depth(&self) -> usize {
+        self.names.len() - 1
+    }
+}
+
+/// Check that `name` survives a display/parse round trip in a `ProjectId`.
+fn check_name(name: &str) -> crate::Result<()> {
+    let message = if name.trim().is_empty() {
+        "project names must not be empty"
+    } else if name.contains(NAME_SEPARATOR) {
+        "project names must not contain `::`"
+    } else if name.trim() != name {
+        "project names must not start or end with whitespace"
+    } else {
+        return Ok(());
+    };
+    Err(crate::Error::InvalidProjectId {
+        input: name.to_string(),
+        message: message.to_string(),
+    })
+}
+
+impl FromStr for ProjectId {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for ProjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.names.iter().any(|name| name.contains('/')) {
+            return f.write_str(&self.names.join("/"));
+        }
+        if self.names.len() == 1 {
+            f.write_str(NAME_SEPARATOR)?;
+        }
+        f.write_str(&self.names.join(NAME_SEPARATOR))
+    }
+}
+
+impl Serialize for ProjectId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A project in a `ProjectIndex`.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedProject {
+    pub id: ProjectId,
+    /// Path from the meta root, `/`-separated (the `build_project_map` key).
+    pub path: String,
+    /// Absolute location of the checkout (see `ProjectPath::resolve`).
+    pub dir: PathBuf,
+    pub info: ProjectInfo,
+}
+
+/// Every project in a meta tree, by `ProjectId`.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectIndex {
+    projects: BTreeMap<ProjectId, IndexedProject>,
+}
+
+impl ProjectIndex {
+    /// Index a tree from `walk_meta_tree` rooted at `base_dir`.
+    ///
+    /// Fails with `Error::InvalidProjectId` if a project name couldn't be
+    /// told apart in a displayed id: an empty name, one containing `::`, or
+    /// one with surrounding whitespace.
+    pub fn build(nodes: &[MetaTreeNode], base_dir: &Path) -> crate::Result<Self> {
+        let mut index = ProjectIndex::default();
+        index.insert(nodes, base_dir, None, "")?;
+        Ok(index)
+    }
+
+    fn insert(
+        &mut self,
+        nodes: &[MetaTreeNode],
+        base_dir: &Path,
+        parent: Option<&ProjectId>,
+        prefix: &str,
+    ) -> crate::Result<()> {
+        for node in nodes {
+            check_name(&node.info.name)?;
+            let id = match parent {
+                Some(parent) => parent.child(&node.info.name),
+                None => ProjectId::root(&node.info.name),
+            };
+            let path = if prefix.is_empty() {
+                node.info.path.clone()
+            } else {
+                format!("{}/{}", prefix, node.info.path)
+            };
+            let dir = ProjectPath::allowing_escape(&path)
+                .map(|p| p.resolve(base_dir))
+                .unwrap_or_else(|_| base_dir.join(&path));
+            self.insert(&node.children, base_dir, Some(&id), &path)?;
+            self.projects.insert(
+                id.clone(),
+                IndexedProject {
+                    id,
+                    path,
+                    dir,
+                    info: node.info.clone(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// The project with id `id`.
+    pub fn get(&self, id: &ProjectId) -> Option<&IndexedProject> {
+        self.projects.get(id)
+    }
+
+    /// All projects, ordered by id (parents before their children).
+    pub fn iter(&self) -> impl Iterator<Item = &IndexedProject> {
+        self.projects.values()
+    }
+
+    /// Number of projects, nested ones included.
+    pub fn len(&self) -> usize {
+        self.projects.len()
+    }
+
+    /// Whether the tree has no projects.
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty()
+    }
+
+    /// Resolve user input (e.g. a CLI argument) to one project.
+    ///
+    /// Tried in order: a full `ProjectId` (`platform/api`, `platform::api`),
+    /// a path from the meta root (`services/platform/api`), and a bare
+    /// project name that is unique across the tree.
+    ///
+    /// Fails with `Error::AmbiguousProject` if a bare name matches several
+    /// projects, or `Error::UnknownProject` if nothing matches.
+    pub fn resolve(&self, input: &str) -> crate::Result<&IndexedProject> {
+        if let Some(project) = ProjectId::parse(input)
+            .ok()
+            .and_then(|id| self.projects.get(&id))
+        {
+            return Ok(project);
+        }
+
+        let path = input.trim().trim_end_matches('/');
+        if let Some(project) = self.iter().find(|p| p.path == path) {
+            return Ok(project);
+        }
+
+        let by_name: Vec<&IndexedProject> = self
+            .iter()
+            .filter(|p| p.id.name() == input.trim())
+            .collect();
+        match by_name.as_slice() {
+            [project] => Ok(project),
+            [] => Err(crate::Error::UnknownProject {
+                input: input.to_string(),
+            }),
+            matches => Err(crate::Error::AmbiguousProject {
+                input: input.to_string(),
+                matches: matches.iter().map(|p| p.id.to_string()).collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{tests::isolated, walk_meta_tree_with};
+
+    #[test]
+    fn test_parse_and_display() {
+        let id: ProjectId = "platform/api".parse().unwrap();
+        assert_eq!(id.names(), ["platform", "api"]);
+        assert_eq!(id, "platform::api".parse().unwrap());
+        assert_eq!(id.to_string(), "platform/api");
+        assert_eq!(id.name(), "api");
+        assert_eq!(id.parent(), Some(ProjectId::root("platform")));
+        assert_eq!(id.depth(), 1);
+
+        let gitmodule = ProjectId::root("vendor").child("libs/api");
+        assert_eq!(gitmodule.to_string(), "vendor::libs/api");
+        assert_eq!(gitmodule, ProjectId::parse(&gitmodule.to_string()).unwrap());
+        assert_eq!(
+            serde_json::to_string(&gitmodule).unwrap(),
+            "\"vendor::libs/api\""
+        );
+
+        let top_level = ProjectId::root("libs/api");
+        assert_eq!(top_level.to_string(), "::libs/api");
+        assert_eq!(top_level, ProjectId::parse(&top_level.to_string()).unwrap());
+        assert_eq!(
+            ProjectId::parse("libs/api").unwrap().names(),
+            ["libs", "api"]
+        );
+
+        assert!(matches!(
+            ProjectId::parse("platform//api"),
+            Err(crate::Error::InvalidProjectId { .. })
+        ));
+        assert!(ProjectId::parse("").is_err());
+    }
+
+    #[test]
+    fn test_index_resolves_own_display() {
+        let node = MetaTreeNode {
+            info: ProjectInfo {
+                name: "libs/api".to_string(),
+                path: "libs/api".to_string(),
+                ..Default::default()
+            },
+            is_meta: false,
+            children: vec![],
+        };
+        let index = ProjectIndex::build(&[node], Path::new("/meta")).unwrap();
+        let project = index.iter().next().unwrap();
+        let displayed = project.id.to_string();
+        assert!(index.get(&ProjectId::parse(&displayed).unwrap()).is_some());
+        assert_eq!(index.resolve(&displayed).unwrap().id, project.id);
+    }
+
+    #[test]
+    fn test_index_rejects_names_that_dont_round_trip() {
+        let node = |name: &str, children| MetaTreeNode {
+            info: ProjectInfo {
+                name: name.to_string(),
+                path: "x".to_string(),
+                ..Default::default()
+            },
+            is_meta: false,
+            children,
+        };
+        let valid = ["api", "libs/api", "team:api", "a-b.c"];
+        for name in valid {
+            let tree = [node("platform", vec![node(name, vec![])])];
+            let index = ProjectIndex::build(&tree, Path::new("/meta")).unwrap();
+            for project in index.iter() {
+                let parsed = ProjectId::parse(&project.id.to_string()).unwrap();
+                assert_eq!(parsed, project.id, "{name}");
+            }
+        }
+
+        for name in ["a::b", "::api", "api::", " api", ""] {
+            let tree = [node("platform", vec![node(name, vec![])])];
+            match ProjectIndex::build(&tree, Path::new("/meta")) {
+                Err(crate::Error::InvalidProjectId { input, .. }) => assert_eq!(input, name),
+                other => panic!("expected InvalidProjectId for {name:?}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_resolves_nested_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        for nested in ["services/platform", "tools"] {
+            std::fs::create_dir_all(dir.path().join(nested)).unwrap();
+            std::fs::write(
+                dir.path().join(nested).join(".meta"),
+                r#"{"projects": {"api": "git@github.com:org/api.git"}}"#,
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            dir.path().join(".meta"),
+            r#"{"projects": {
+                "platform": {"repo": "git@github.com:org/platform.git", "path": "services/platform", "meta": true},
+                "tools": "git@github.com:org/tools.git"
+            }}"#,
+        )
+        .unwrap();
+
+        let tree = walk_meta_tree_with(dir.path(), None, &isolated(dir.path())).unwrap();
+        let index = ProjectIndex::build(&tree, dir.path()).unwrap();
+        assert_eq!(index.len(), 4);
+
+        let api = index.resolve("platform/api").unwrap();
+        assert_eq!(api.path, "services/platform/api");
+        assert_eq!(api.dir, dir.path().join("services/platform/api"));
+        assert_eq!(index.resolve("platform::api").unwrap().id, api.id);
+        assert_eq!(index.resolve("services/platform/api").unwrap().id, api.id);
+        assert_eq!(index.resolve("tools").unwrap().id, ProjectId::root("tools"));
+
+        match index.resolve("api") {
+            Err(crate::Error::AmbiguousProject { matches, .. }) => {
+                assert_eq!(matches, vec!["platform/api", "tools/api"]);
+            }
+            other => panic!("expected AmbiguousProject, got {other:?}"),
+        }
+        assert!(matches!(
+            index.resolve("web"),
+            Err(crate::Error::UnknownProject { .. })
+        ));
+    }
+}
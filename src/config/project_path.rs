@@ -0,0 +1,396 @@
+//! Validated project paths relative to a meta root.
+//!
+//! A project's `path` comes straight from a .meta file (after `${VAR}`
+//! interpolation and overlays), so it may be absolute, contain `..`, or use
+//! `\` separators. `ProjectPath` normalizes it and, when constructed with
+//! `ProjectPath::new`, guarantees it stays below the meta root.
+
+use super::ProjectInfo;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Why a project path was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProjectPathError {
+    /// The path is empty or refers to the meta root itself.
+    #[error("path refers to the meta root itself")]
+    Empty,
+    /// The path is absolute.
+    #[error("path is absolute")]
+    Absolute,
+    /// The path climbs above the meta root via `..`.
+    #[error("path escapes the meta root")]
+    EscapesRoot,
+}
+
+/// A normalized project path relative to the meta root.
+///
+/// Separators are `/`, and `.` segments, empty segments and `..` segments
+/// that can be folded into a preceding component are removed, so
+/// `./libs//core/../api` becomes `libs/api`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct ProjectPath(String);
+
+impl ProjectPath {
+    /// Normalize `raw`, rejecting empty, absolute and escaping paths.
+    pub fn new(raw: &str) -> Result<Self, ProjectPathError> {
+        let path = Self::allowing_escape(raw)?;
+        if is_absolute(&path.0) {
+            Err(ProjectPathError::Absolute)
+        } else if path.escapes_root() {
+            Err(ProjectPathError::EscapesRoot)
+        } else {
+            Ok(path)
+        }
+    }
+
+    /// Normalize `raw`, keeping absolute paths and leading `..` segments.
+    ///
+    /// For projects deliberately checked out next to the meta repo
+    /// (e.g. `../shared-lib`). Parsing normalizes paths this way; strict
+    /// parsing and `validate` reject such paths in .meta files.
+    pub fn allowing_escape(raw: &str) -> Result<Self, ProjectPathError> {
+        let raw = raw.replace('\\', "/");
+        let (prefix, rest) = split_root(&raw);
+
+        let mut parts: Vec<&str> = Vec::new();
+        for part in rest.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => match parts.last() {
+                    Some(&last) if last != ".." => {
+                        parts.pop();
+                    }
+                    // `..` at the root of an absolute path stays at the root
+                    _ if !prefix.is_empty() => {}
+                    _ => parts.push(".."),
+                },
+                part => parts.push(part),
+            }
+        }
+        if parts.is_empty() {
+            return Err(ProjectPathError::Empty);
+        }
+        Ok(ProjectPath(format!("{prefix}{}", parts.join("/"))))
+    }
+
+    /// The normalized, `/`-separated path.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the path is absolute or leads out of the meta root.
+    pub fn escapes_root(&self) -> bool {
+        is_absolute(&self.0) || self.0 == ".." || self.0.starts_with("../")
+    }
+
+    /// Whether `other` lies strictly inside this path.
+    pub fn contains(&self, other: &ProjectPath) -> bool {
+        other
+            .0
+            .strip_prefix(&self.0)
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// The absolute location of the project for the meta repo at `meta_root`.
+    ///
+    /// A relative `meta_root` is taken relative to the current directory.
+    /// The result is normalized lexically; symlinks are not resolved, so the
+    /// directory doesn't need to exist.
+    pub fn resolve(&self, meta_root: &Path) -> PathBuf {
+        let base = std::path::absolute(meta_root).unwrap_or_else(|_| meta_root.to_path_buf());
+        let mut resolved = PathBuf::new();
+        for component in base.join(&self.0).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        resolved.push(component);
+                    }
+                }
+                component => resolved.push(component),
+            }
+        }
+        resolved
+    }
+}
+
+impl fmt::Display for ProjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ProjectPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// How two projects' directories collide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathConflictKind {
+    /// Both projects use the same directory.
+    Duplicate,
+    /// One project's directory is inside the other's.
+    Nested,
+}
+
+/// Two projects whose directories collide.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathConflict {
+    pub kind: PathConflictKind,
+    /// The later project by name, or the inner one for `Nested`.
+    pub project: String,
+    /// The earlier project by name, or the outer one for `Nested`.
+    pub other: String,
+    /// The path of `project`.
+    pub path: ProjectPath,
+}
+
+/// Find projects that share a directory or live inside another project.
+///
+/// Paths that can't be normalized are skipped. Conflicts are sorted by
+/// project name.
+pub fn path_conflicts(projects: &[ProjectInfo]) -> Vec<PathConflict> {
+    conflicts(projects.iter().filter_map(|project| {
+        ProjectPath::allowing_escape(&project.path)
+            .ok()
+            .map(|path| (project.name.as_str(), path))
+    }))
+}
+
+pub(super) fn conflicts<'a>(
+    paths: impl IntoIterator<Item = (&'a str, ProjectPath)>,
+) -> Vec<PathConflict> {
+    let mut paths: Vec<(&str, ProjectPath)> = paths.into_iter().collect();
+    paths.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut conflicts = Vec::new();
+    for (i, (name, path)) in paths.iter().enumerate() {
+        for (other, other_path) in &paths[..i] {
+            if path == other_path {
+                conflicts.push(PathConflict {
+                    kind: PathConflictKind::Duplicate,
+                    project: name.to_string(),
+                    other: other.to_string(),
+                    path: path.clone(),
+                });
+            }
+        }
+        for (other, other_path) in &paths {
+            if other_path.contains(path) {
+                conflicts.push(PathConflict {
+                    kind: PathConflictKind::Nested,
+                    project: name.to_string(),
+                    other: other.to_string(),
+                    path: path.clone(),
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+/// Split a leading `/` or Windows drive (`C:/`) off a `/`-separated path.
+fn split_root(path: &str) -> (&str, &str) {
+    if path.starts_with('/') {
+        path.split_at(1)
+    } else if has_drive(path) {
+        path.split_at(3)
+    } else {
+        ("", path)
+    }
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || has_drive(path)
+}
+
+/// Whether `path` starts with a drive root like `C:/`.
+///
+/// A bare `C:` is drive-relative on Windows and an ordinary directory name
+/// elsewhere, so it's treated as relative.
+fn has_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'/'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("api", "api"),
+            ("./libs//core/../api/", "libs/api"),
+            ("libs\\core", "libs/core"),
+            ("a/b/../../c", "c"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(ProjectPath::new(raw).unwrap().as_str(), expected, "{raw}");
+        }
+
+        assert_eq!(ProjectPath::new(""), Err(ProjectPathError::Empty));
+        assert_eq!(ProjectPath::new("a/.."), Err(ProjectPathError::Empty));
+        assert_eq!(
+            ProjectPath::new("/srv/api"),
+            Err(ProjectPathError::Absolute)
+        );
+        assert_eq!(ProjectPath::new("C:\\api"), Err(ProjectPathError::Absolute));
+        assert_eq!(ProjectPath::new("c:api").unwrap().as_str(), "c:api");
+        assert_eq!(
+            ProjectPath::new("x:/../y").unwrap_err(),
+            ProjectPathError::Absolute
+        );
+        assert_eq!(
+            ProjectPath::new("a/../../b"),
+            Err(ProjectPathError::EscapesRoot)
+        );
+    }
+
+    #[test]
+    fn test_allowing_escape() {
+        let sibling = ProjectPath::allowing_escape("libs/../../shared/./lib").unwrap();
+        assert_eq!(sibling.as_str(), "../shared/lib");
+        assert!(sibling.escapes_root());
+        assert_eq!(
+            ProjectPath::allowing_escape("/srv/../../api")
+                .unwrap()
+                .as_str(),
+            "/api"
+        );
+        assert_eq!(
+            ProjectPath::allowing_escape("C:/x/../y").unwrap().as_str(),
+            "C:/y"
+        );
+        assert!(!ProjectPath::new("api").unwrap().escapes_root());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = Path::new("/work/meta");
+        assert_eq!(
+            ProjectPath::new("libs/api").unwrap().resolve(root),
+            PathBuf::from("/work/meta/libs/api")
+        );
+        assert_eq!(
+            ProjectPath::allowing_escape("../shared")
+                .unwrap()
+                .resolve(root),
+            PathBuf::from("/work/shared")
+        );
+        assert!(ProjectPath::new("api")
+            .unwrap()
+            .resolve(Path::new("meta"))
+            .is_absolute());
+    }
+
+    #[test]
+    fn test_parse_meta_config_checks_paths() {
+        use crate::config::{
+            parse_meta_config_with, tests::isolated, walk_meta_tree_with, DiagnosticKind,
+            ParseOptions,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let options = isolated(dir.path());
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  api:\n    repo: git@github.com:org/api.git\n    path: ./services//api/\n",
+        )
+        .unwrap();
+        let (projects, _) = parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(projects[0].path, "services/api");
+
+        std::fs::write(
+            &meta,
+            "projects:\n  shared:\n    repo: git@github.com:org/shared.git\n    path: ../shared\n",
+        )
+        .unwrap();
+        // Paths outside the meta root are accepted unless parsing strictly
+        let (projects, _) = parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(projects[0].path, "../shared");
+        let tree = walk_meta_tree_with(dir.path(), None, &options).unwrap();
+        assert_eq!(tree[0].info.path, "../shared");
+        let strict = ParseOptions {
+            strict: true,
+            ..options.clone()
+        };
+        match parse_meta_config_with(&meta, &strict) {
+            Err(crate::Error::ConfigInvalid { diagnostics, .. }) => {
+                assert_eq!(diagnostics[0].kind, DiagnosticKind::PathEscapesRoot);
+            }
+            other => panic!("expected ConfigInvalid, got {other:?}"),
+        }
+
+        std::fs::write(
+            &meta,
+            "projects:\n  root:\n    repo: git@github.com:org/root.git\n    path: ./\n",
+        )
+        .unwrap();
+        // The meta root itself is kept as written unless parsing strictly
+        let (projects, _) = parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(projects[0].path, "./");
+        let diagnostics = crate::config::validate(&meta).unwrap();
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::PathEscapesRoot);
+        assert!(matches!(
+            parse_meta_config_with(&meta, &strict),
+            Err(crate::Error::ConfigInvalid { .. })
+        ));
+
+        // Strict parsing checks paths after `${VAR}` interpolation
+        std::fs::write(
+            &meta,
+            "projects:\n  api:\n    repo: git@github.com:org/api.git\n    path: ${API_DIR}\n",
+        )
+        .unwrap();
+        let mut strict = strict.clone();
+        for (value, ok) in [("services/api", true), ("/etc", false), ("../../x", false)] {
+            strict.sources.env = Some([("API_DIR".to_string(), value.to_string())].into());
+            match parse_meta_config_with(&meta, &strict) {
+                Ok((projects, _)) if ok => assert_eq!(projects[0].path, value),
+                Err(crate::Error::ConfigInvalid { diagnostics, .. }) if !ok => {
+                    assert_eq!(diagnostics[0].kind, DiagnosticKind::PathEscapesRoot);
+                    assert!(diagnostics[0].message.contains(value));
+                }
+                other => panic!("unexpected result for `{value}`: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let project = |name: &str, path: &str| ProjectInfo {
+            name: name.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let projects = [
+            project("api", "services/api"),
+            project("api-v2", "./services/api/"),
+            project("services", "services"),
+            project("apis", "services/apis"),
+            project("web", "web"),
+        ];
+        let conflicts = path_conflicts(&projects);
+        let found: Vec<(PathConflictKind, &str, &str)> = conflicts
+            .iter()
+            .map(|c| (c.kind, c.project.as_str(), c.other.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (PathConflictKind::Nested, "api", "services"),
+                (PathConflictKind::Duplicate, "api-v2", "api"),
+                (PathConflictKind::Nested, "api-v2", "services"),
+                (PathConflictKind::Nested, "apis", "services"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! A small query language for selecting projects.
+//!
+//! ```text
+//! frontend && !legacy
+//! (api || worker) && team:payments
+//! name:auth-* || path:services/
+//! ```
+//!
+//! - A bare word matches a tag exactly (tags may contain `:`, e.g. `team:payments`)
+//! - `name:<glob>` matches the project name against a glob (`*`, `?`, `[...]`)
+//! - `path:<prefix>` matches projects at or below a path (whole components
+//!   only, normalized like project paths, so `path:.` matches everything)
+//! - `tag:<tag>` matches a tag explicitly (for tags that start with `name:` or `path:`)
+//! - `!` negates, `&&` binds tighter than `||`, and parentheses group
+
+use super::{MetaTreeNode, ProjectInfo, ProjectPath};
+use std::fmt;
+use std::str::FromStr;
+
+/// How deeply `!` and parentheses may nest, so a hostile query (e.g. from
+/// `META_FILTER`) can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed project selection expression.
+#[derive(Debug, Clone)]
+pub struct ProjectQuery {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Tag(String),
+    Name(glob::Pattern),
+    Path(String),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl ProjectQuery {
+    /// Parse a query expression.
+    ///
+    /// Fails with `Error::QueryParse` pointing at the offending position.
+    pub fn parse(input: &str) -> crate::Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            input,
+            tokens: &tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error(token.offset, "unexpected token"));
+        }
+        Ok(Self {
+            source: input.to_string(),
+            expr,
+        })
+    }
+
+    /// Check a project against the query, using its own `path`.
+    pub fn matches(&self, project: &ProjectInfo) -> bool {
+        self.matches_at(project, &project.path)
+    }
+
+    /// Check a project against the query, matching `path:` atoms against
+    /// `full_path` (e.g. the nested path from `flatten_meta_tree`).
+    pub fn matches_at(&self, project: &ProjectInfo, full_path: &str) -> bool {
+        self.expr.eval(project, full_path)
+    }
+
+    /// Select the matching projects from a list.
+    pub fn filter<'a>(&self, projects: &'a [ProjectInfo]) -> Vec<&'a ProjectInfo> {
+        projects.iter().filter(|p| self.matches(p)).collect()
+    }
+
+    /// Select the matching projects from a meta tree, including nested children.
+    ///
+    /// Returns `(full_path, project)` pairs in the same order as `flatten_meta_tree`.
+    pub fn filter_tree<'a>(&self, nodes: &'a [MetaTreeNode]) -> Vec<(String, &'a ProjectInfo)> {
+        fn walk<'a>(
+            query: &ProjectQuery,
+            nodes: &'a [MetaTreeNode],
+            prefix: &str,
+            out: &mut Vec<(String, &'a ProjectInfo)>,
+        ) {
+            for node in nodes {
+                let full_path = if prefix.is_empty() {
+                    node.info.path.clone()
+                } else {
+                    format!("{}/{}", prefix, node.info.path)
+                };
+                if query.matches_at(&node.info, &full_path) {
+                    out.push((full_path.clone(), &node.info));
+                }
+                walk(query, &node.children, &full_path, out);
+            }
+        }
+        let mut out = Vec::new();
+        walk(self, nodes, "", &mut out);
+        out
+    }
+}
+
+impl FromStr for ProjectQuery {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for ProjectQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    fn eval(&self, project: &ProjectInfo, full_path: &str) -> bool {
+        match self {
+            Expr::Tag(tag) => project.tags.iter().any(|t| t == tag),
+            Expr::Name(pattern) => pattern.matches(&project.name),
+            Expr::Path(prefix) => {
+                let path = full_path.trim_end_matches('/');
+                prefix.is_empty()
+                    || path == prefix
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }
+            Expr::Not(inner) => !inner.eval(project, full_path),
+            Expr::And(terms) => terms.iter().all(|t| t.eval(project, full_path)),
+            Expr::Or(terms) => terms.iter().any(|t| t.eval(project, full_path)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Byte offset of the token in the input.
+    offset: usize,
+}
+
+fn query_error(input: &str, position: usize, message: &str) -> crate::Error {
+    crate::Error::QueryParse {
+        query: input.to_string(),
+        position,
+        message: message.to_string(),
+    }
+}
+
+fn tokenize(input: &str) -> crate::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '!' => TokenKind::Not,
+            '&' | '|' => {
+                if chars.next_if(|&(_, next)| next == c).is_none() {
+                    return Err(query_error(input, offset, &format!("expected `{c}{c}`")));
+                }
+                if c == '&' {
+                    TokenKind::And
+                } else {
+                    TokenKind::Or
+                }
+            }
+            _ => {
+                let mut end = offset + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_whitespace() || "()!&|".contains(next) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                TokenKind::Word(input[offset..end].to_string())
+            }
+        };
+        tokens.push(Token { kind, offset });
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser: `or := and ("||" and)*`, `and := unary ("&&" unary)*`,
+/// `unary := "!" unary | "(" or ")" | atom`.
+///
+/// Chains of `&&` and `||` become one node each, so only `!` and
+/// parentheses nest (up to `MAX_DEPTH`).
+struct Parser<'a> {
+    input: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+    /// Current nesting of `!` and parentheses.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|t| &t.kind == kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, position: usize, message: &str) -> crate::Error {
+        query_error(self.input, position, message)
+    }
+
+    fn or(&mut self) -> crate::Result<Expr> {
+        let mut terms = vec![self.and()?];
+        while self.eat(&TokenKind::Or) {
+            terms.push(self.and()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Expr::Or(terms),
+        })
+    }
+
+    fn and(&mut self) -> crate::Result<Expr> {
+        let mut terms = vec![self.unary()?];
+        while self.eat(&TokenKind::And) {
+            terms.push(self.unary()?);
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Expr::And(terms),
+        })
+    }
+
+    /// Parse with `f` one level deeper, failing at `offset` past `MAX_DEPTH`.
+    fn nested(
+        &mut self,
+        offset: usize,
+        f: impl FnOnce(&mut Self) -> crate::Result<Expr>,
+    ) -> crate::Result<Expr> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(offset, "too deeply nested"));
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn unary(&mut self) -> crate::Result<Expr> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error(self.input.len(), "expected a tag, `name:`, `path:` or `(`"));
+        };
+        self.pos += 1;
+        match token.kind {
+            TokenKind::Not => self.nested(token.offset, |parser| {
+                Ok(Expr::Not(Box::new(parser.unary()?)))
+            }),
+            TokenKind::Open => self.nested(token.offset, |parser| {
+                let expr = parser.or()?;
+                if !parser.eat(&TokenKind::Close) {
+                    let position = parser.peek().map_or(parser.input.len(), |t| t.offset);
+                    return Err(parser.error(position, "expected `)`"));
+                }
+                Ok(expr)
+            }),
+            TokenKind::Word(word) => self.atom(&word, token.offset),
+            _ => Err(self.error(token.offset, "expected a tag, `name:`, `path:` or `(`")),
+        }
+    }
+
+    fn atom(&self, word: &str, offset: usize) -> crate::Result<Expr> {
+        if let Some(glob) = word.strip_prefix("name:") {
+            let pattern = glob::Pattern::new(glob)
+                .map_err(|e| self.error(offset + "name:".len() + e.pos, e.msg))?;
+            Ok(Expr::Name(pattern))
+        } else if let Some(prefix) = word.strip_prefix("path:") {
+            // The meta root itself (`path:`, `path:.`) contains every project
+            let prefix = ProjectPath::allowing_escape(prefix)
+                .map(|path| path.as_str().to_string())
+                .unwrap_or_default();
+            Ok(Expr::Path(prefix))
+        } else if let Some(tag) = word.strip_prefix("tag:") {
+            Ok(Expr::Tag(tag.to_string()))
+        } else {
+            Ok(Expr::Tag(word.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, path: &str, tags: &[&str]) -> ProjectInfo {
+        ProjectInfo {
+            name: name.to_string(),
+            path: path.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn projects() -> Vec<ProjectInfo> {
+        vec![
+            project("web", "apps/web", &["frontend"]),
+            project("old-web", "apps/old-web", &["frontend", "legacy"]),
+            project("api", "services/api", &["api", "team:payments"]),
+            project("worker", "services/worker", &["worker", "team:search"]),
+            project("auth-api", "services/auth", &["api", "team:identity"]),
+        ]
+    }
+
+    fn select(query: &str) -> Vec<String> {
+        let projects = projects();
+        ProjectQuery::parse(query)
+            .unwrap()
+            .filter(&projects)
+            .into_iter()
+            .map(|p| p.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_tags_and_operators() {
+        assert_eq!(select("frontend && !legacy"), vec!["web"]);
+        assert_eq!(select("(api || worker) && team:payments"), vec!["api"]);
+        assert_eq!(
+            select("api || worker && team:search"),
+            vec!["api", "worker", "auth-api"]
+        );
+        assert_eq!(select("!!legacy"), vec!["old-web"]);
+    }
+
+    #[test]
+    fn test_name_globs_and_path_prefixes() {
+        assert_eq!(select("name:*api"), vec!["api", "auth-api"]);
+        assert_eq!(
+            select("path:services/ && !name:auth-*"),
+            vec!["api", "worker"]
+        );
+        // Prefixes match whole path components only
+        assert_eq!(select("path:apps/web"), vec!["web"]);
+        assert_eq!(select("tag:team:search"), vec!["worker"]);
+
+        // Prefixes are normalized like project paths
+        let all = select("name:*");
+        for root in ["path:", "path:.", "path:./", "path:apps/.."] {
+            assert_eq!(select(root), all, "{root}");
+        }
+        assert_eq!(select("path:./services//auth/"), vec!["auth-api"]);
+        assert_eq!(select("path:services\\worker"), vec!["worker"]);
+    }
+
+    #[test]
+    fn test_nesting_is_limited() {
+        let deep = format!("{}api", "!".repeat(MAX_DEPTH));
+        assert_eq!(select(&deep), select("api"));
+        let parens = format!("{}api{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(select(&parens), select("api"));
+
+        for query in [
+            format!("{}api", "!".repeat(100_000)),
+            format!("{}api", "(".repeat(100_000)),
+        ] {
+            match ProjectQuery::parse(&query) {
+                Err(crate::Error::QueryParse {
+                    position, message, ..
+                }) => {
+                    assert_eq!(position, MAX_DEPTH);
+                    assert!(message.contains("nested"), "{message}");
+                }
+                other => panic!("expected QueryParse, got {other:?}"),
+            }
+        }
+
+        // Long chains don't nest
+        let chain = vec!["legacy"; 100_000].join(" || ");
+        assert_eq!(select(&chain), vec!["old-web"]);
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let cases = [
+            ("frontend &&", 11),
+            ("(api || worker", 14),
+            ("api & worker", 4),
+            ("api worker", 4),
+            ("", 0),
+        ];
+        for (query, expected) in cases {
+            match ProjectQuery::parse(query) {
+                Err(crate::Error::QueryParse { position, .. }) => {
+                    assert_eq!(position, expected, "query: {query:?}")
+                }
+                other => panic!("expected QueryParse for {query:?}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_tree_uses_full_paths() {
+        let tree = vec![MetaTreeNode {
+            info: project("vendor", "vendor", &["third-party"]),
+            is_meta: true,
+            children: vec![MetaTreeNode {
+                info: project("lib", "lib", &["rust"]),
+                is_meta: false,
+                children: vec![],
+            }],
+        }];
+        let query: ProjectQuery = "path:vendor/lib || third-party".parse().unwrap();
+        let selected: Vec<String> = query
+            .filter_tree(&tree)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(selected, vec!["vendor", "vendor/lib"]);
+        assert_eq!(query.to_string(), "path:vendor/lib || third-party");
+    }
+}
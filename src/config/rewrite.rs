@@ -0,0 +1,190 @@
+//! Rewriting of project repo URLs.
+//!
+//! Like git's `url.<base>.insteadOf`, `url_rewrites` maps a URL prefix to its
+//! replacement, e.g. to clone over HTTPS on machines without SSH keys:
+//!
+//! ```yaml
+//! url_rewrites:
+//!   "git@github.com:": "https://github.com/"
+//! ```
+//!
+//! Rules come from the user config (`~/.meta/config.yaml`) and the .meta file;
+//! a .meta rule replaces a user rule for the same prefix. When several
+//! prefixes match, the longest wins, and only one rule is applied.
+//!
+//! With `github_shorthand: true` in the .meta file, GitHub shorthand
+//! `org/repo` is expanded to `https://github.com/org/repo.git` before
+//! rewriting. Repos that are local paths must then start with `./`, `../`
+//! or `/`; without it, `org/repo` is left alone.
+
+use super::{MetaConfig, ProjectEntry};
+use std::collections::BTreeMap;
+
+/// Apply the longest matching rewrite rule to `url`, first expanding GitHub
+/// shorthand if `github_shorthand` is set.
+pub fn resolve_repo_url(
+    url: &str,
+    rules: &BTreeMap<String, String>,
+    github_shorthand: bool,
+) -> String {
+    let url = github_shorthand
+        .then(|| expand_shorthand(url))
+        .flatten()
+        .unwrap_or_else(|| url.to_string());
+    let rule = rules
+        .iter()
+        .filter(|(prefix, _)| !prefix.is_empty() && url.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len());
+    match rule {
+        Some((prefix, replacement)) => format!("{replacement}{}", &url[prefix.len()..]),
+        None => url,
+    }
+}
+
+/// Resolve the repo URL of every project in `config`.
+///
+/// `user_rules` are overridden by the config's own `url_rewrites`.
+pub(super) fn apply(config: &mut MetaConfig, user_rules: &BTreeMap<String, String>) {
+    let mut rules = user_rules.clone();
+    rules.extend(config.url_rewrites.clone());
+
+    for entry in config.projects.values_mut() {
+        let repo = match entry {
+            ProjectEntry::Simple(url) => url,
+            ProjectEntry::Extended {
+                repo: Some(url), ..
+            } => url,
+            ProjectEntry::Extended { repo: None, .. } => continue,
+        };
+        *repo = resolve_repo_url(repo, &rules, config.github_shorthand);
+    }
+}
+
+/// `org/repo` -> `https://github.com/org/repo.git`, if `url` is shorthand.
+fn expand_shorthand(url: &str) -> Option<String> {
+    let (org, repo) = url.split_once('/')?;
+    let is_part = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    if !is_part(org) || !is_part(repo) {
+        return None;
+    }
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    Some(format!("https://github.com/{org}/{repo}.git"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_repo_url() {
+        let rules = rules(&[
+            ("git@github.com:", "https://github.com/"),
+            (
+                "git@github.com:internal/",
+                "https://git.corp.example/internal/",
+            ),
+        ]);
+        let cases = [
+            (
+                "git@github.com:org/api.git",
+                "https://github.com/org/api.git",
+            ),
+            (
+                "git@github.com:internal/auth.git",
+                "https://git.corp.example/internal/auth.git",
+            ),
+            ("org/web", "https://github.com/org/web.git"),
+            ("org/web.git", "https://github.com/org/web.git"),
+            ("../local/repo", "../local/repo"),
+            (
+                "https://gitlab.com/org/x.git",
+                "https://gitlab.com/org/x.git",
+            ),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(resolve_repo_url(url, &rules, true), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_shorthand_is_opt_in() {
+        let rules = rules(&[("git@github.com:", "https://github.com/")]);
+        assert_eq!(
+            resolve_repo_url("mirrors/api", &rules, false),
+            "mirrors/api"
+        );
+        assert_eq!(
+            resolve_repo_url("git@github.com:org/api.git", &rules, false),
+            "https://github.com/org/api.git"
+        );
+
+        // A relative-path repo stays as written unless the file opts in
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(&meta, "projects:\n  api: mirrors/api\n").unwrap();
+        let options = crate::config::tests::isolated(dir.path());
+        let config = crate::config::load_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            config.projects["api"],
+            ProjectEntry::Simple("mirrors/api".to_string())
+        );
+
+        std::fs::write(
+            &meta,
+            "github_shorthand: true\nprojects:\n  api: mirrors/api\n",
+        )
+        .unwrap();
+        let config = crate::config::load_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            config.projects["api"],
+            ProjectEntry::Simple("https://github.com/mirrors/api.git".to_string())
+        );
+        assert!(crate::config::validate(&meta).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_shorthand_is_rewritten_too() {
+        let rules = rules(&[("https://github.com/", "git@github.com:")]);
+        assert_eq!(
+            resolve_repo_url("org/api", &rules, true),
+            "git@github.com:org/api.git"
+        );
+    }
+
+    #[test]
+    fn test_meta_rules_override_user_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "github_shorthand: true\nprojects:\n  api: git@github.com:org/api.git\n  web: org/web\n  local:\n    path: local\nurl_rewrites:\n  \"git@github.com:\": \"ssh://git@mirror.example/\"\n",
+        )
+        .unwrap();
+        let mut config = crate::config::read_meta_config(&meta).unwrap();
+        apply(
+            &mut config,
+            &rules(&[("git@github.com:", "https://github.com/")]),
+        );
+        assert_eq!(
+            config.projects["api"],
+            ProjectEntry::Simple("ssh://git@mirror.example/org/api.git".to_string())
+        );
+        assert_eq!(
+            config.projects["web"],
+            ProjectEntry::Simple("https://github.com/org/web.git".to_string())
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! User-level settings shared by every meta repo on a machine.
+//!
+//! Read from `config.yaml` in the meta data directory (`~/.meta/config.yaml`,
+//! or under `META_DATA_DIR`). A missing file means no user settings.
+//!
+//! Parsing reads it through `ConfigSources`, so callers can pass a config
+//! they already loaded, or point parsing at another meta data directory.
+
+use super::{diagnostics, PartialDefaults};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// File name of the user config in the meta data directory.
+const USER_CONFIG_FILE: &str = "config.yaml";
+
+/// Settings from the user config file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// Repo URL prefix rewrites, applied to every meta repo (see `resolve_repo_url`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub url_rewrites: BTreeMap<String, String>,
+    /// Defaults for every meta repo; a repo's .meta overrides them.
+    #[serde(default)]
+    pub defaults: PartialDefaults,
+}
+
+/// Where parsing reads user-level settings from.
+///
+/// The default reads the real ones: the user config and overlays (see
+/// `overlay_path`) in `meta_dir()`, and the process environment for
+/// `${VAR}` references, `META_*` defaults and `META_PROFILE`. Tests and
+/// callers that load these themselves set the fields instead.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// The meta data directory holding the user config and overlays;
+    /// `meta_dir()` if `None`.
+    pub meta_dir: Option<PathBuf>,
+    /// The user config to apply instead of loading it from `meta_dir`.
+    pub user_config: Option<UserConfig>,
+    /// Environment variables to use instead of the process environment.
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+impl ConfigSources {
+    /// Sources confined to `meta_dir`, with no environment variables set.
+    pub fn isolated(meta_dir: impl Into<PathBuf>) -> Self {
+        ConfigSources {
+            meta_dir: Some(meta_dir.into()),
+            user_config: None,
+            env: Some(BTreeMap::new()),
+        }
+    }
+
+    /// The value of environment variable `name`.
+    pub(super) fn var(&self, name: &str) -> Option<String> {
+        match &self.env {
+            Some(env) => env.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    /// The meta data directory.
+    pub(super) fn meta_dir(&self) -> PathBuf {
+        self.meta_dir.clone().unwrap_or_else(crate::meta_dir)
+    }
+
+    /// The user config and the path it is reported under.
+    ///
+    /// A user config file that fails to load is skipped unless `strict`, so
+    /// one broken file doesn't fail every parse on the machine.
+    pub(super) fn user_config(&self, strict: bool) -> crate::Result<(UserConfig, PathBuf)> {
+        let path = self.meta_dir().join(USER_CONFIG_FILE);
+        let config = match &self.user_config {
+            Some(config) => config.clone(),
+            None => match UserConfig::load_from(&path) {
+                Ok(config) => config,
+                Err(_) if !strict => UserConfig::default(),
+                Err(e) => return Err(e),
+            },
+        };
+        Ok((config, path))
+    }
+}
+
+impl UserConfig {
+    /// Path of the user config file.
+    pub fn path() -> PathBuf {
+        crate::meta_dir().join(USER_CONFIG_FILE)
+    }
+
+    /// Load the user config from its default location.
+    pub fn load() -> crate::Result<Self> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Load a user config file (YAML, or JSON as a subset of it).
+    ///
+    /// Returns the default config if the file doesn't exist.
+    pub fn load_from(path: &Path) -> crate::Result<Self> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(crate::Error::io(path, e)),
+        };
+        if source.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml_ng::from_str(&source).map_err(|e| diagnostics::yaml_error(path, &source, &e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_user_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        assert_eq!(UserConfig::load_from(&path).unwrap(), UserConfig::default());
+
+        std::fs::write(
+            &path,
+            "url_rewrites:\n  \"git@github.com:\": \"https://github.com/\"\n",
+        )
+        .unwrap();
+        let config = UserConfig::load_from(&path).unwrap();
+        assert_eq!(
+            config.url_rewrites["git@github.com:"],
+            "https://github.com/"
+        );
+
+        std::fs::write(&path, "url_rewrite: {}\n").unwrap();
+        match UserConfig::load_from(&path) {
+            Err(crate::Error::ConfigParse { line, message, .. }) => {
+                assert_eq!(line, Some(1));
+                assert!(message.contains("unknown field `url_rewrite`"), "{message}");
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_uses_given_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta");
+        std::fs::write(
+            &meta,
+            r#"{"projects": {"api": "git@github.com:org/api.git"}}"#,
+        )
+        .unwrap();
+        let meta_dir = dir.path().join("meta-data");
+        std::fs::create_dir(&meta_dir).unwrap();
+        std::fs::write(meta_dir.join(USER_CONFIG_FILE), "url_rewrite: {}\n").unwrap();
+
+        let mut options = crate::config::ParseOptions {
+            sources: ConfigSources::isolated(&meta_dir),
+            ..Default::default()
+        };
+        // A broken user config is skipped unless parsing strictly
+        let (projects, _) = crate::config::parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("git@github.com:org/api.git")
+        );
+        let strict = crate::config::ParseOptions {
+            strict: true,
+            ..options.clone()
+        };
+        assert!(matches!(
+            crate::config::parse_meta_config_with(&meta, &strict),
+            Err(crate::Error::ConfigParse { .. })
+        ));
+
+        // A config passed in replaces the file
+        options.sources.user_config = Some(UserConfig {
+            url_rewrites: BTreeMap::from([(
+                "git@github.com:".to_string(),
+                "https://github.com/".to_string(),
+            )]),
+            ..Default::default()
+        });
+        let (projects, _) = crate::config::parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("https://github.com/org/api.git")
+        );
+    }
+
+    #[test]
+    fn test_parse_uses_given_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = dir.path().join(".meta.yaml");
+        std::fs::write(
+            &meta,
+            "projects:\n  api: ${ORG_URL}/api.git\n  web: ${ORG_URL}/web.git\nprofiles:\n  api-only:\n    filter: name:api\n",
+        )
+        .unwrap();
+        let mut sources = ConfigSources::isolated(dir.path().join("meta-data"));
+        sources.env = Some(BTreeMap::from([
+            ("ORG_URL".to_string(), "git@github.com:org".to_string()),
+            ("META_PROFILE".to_string(), "api-only".to_string()),
+            ("META_FAIL_FAST".to_string(), "yes".to_string()),
+        ]));
+        let options = crate::config::ParseOptions {
+            sources,
+            ..Default::default()
+        };
+
+        let config = crate::config::load_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(config.projects.keys().collect::<Vec<_>>(), vec!["api"]);
+        assert!(config.defaults.fail_fast);
+        let (projects, _) = crate::config::parse_meta_config_with(&meta, &options).unwrap();
+        assert_eq!(
+            projects[0].repo.as_deref(),
+            Some("git@github.com:org/api.git")
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Semantic validation of .meta files.
+//!
+//! A config can parse successfully and still be wrong: serde ignores unknown
+//! keys (so `depend_on` or `tag` typos go unnoticed), and nothing checks that
+//! paths stay inside the meta root or that `depends_on` names resolve.
+//! `validate` reports these problems as `Diagnostic`s; `ParseOptions::strict`
+//! turns them into a hard parse failure.
+
+use super::project_path::{self, PathConflictKind, ProjectPath};
+use super::{document, include, ConfigFormat, MetaConfig, ProjectEntry};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Keys accepted at the top level of a .meta file.
+pub(super) const TOP_LEVEL_KEYS: &[&str] = &[
+    "projects",
+    "ignore",
+    "defaults",
+    "worktrees_dir",
+    "include",
+    "url_rewrites",
+    "github_shorthand",
+    "profiles",
+];
+
+/// Alternative spellings accepted for top-level keys (serde aliases).
+pub(super) const TOP_LEVEL_ALIASES: &[&str] = &["extends"];
+
+/// Keys accepted in an extended project entry.
+pub(super) const PROJECT_KEYS: &[&str] =
+    &["repo", "path", "tags", "provides", "depends_on", "meta"];
+
+/// Keys accepted in the `defaults` section.
+pub(super) const DEFAULTS_KEYS: &[&str] = &[
+    "parallel",
+    "max_concurrency",
+    "filter",
+    "shell",
+    "timeout_secs",
+    "fail_fast",
+];
+
+/// The kind of problem a `Diagnostic` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A key that the config format doesn't define (usually a typo).
+    UnknownKey,
+    /// Two projects resolve to the same directory.
+    DuplicatePath,
+    /// A project's directory is inside another project's directory.
+    NestedPath,
+    /// A project's `repo` is an empty string.
+    EmptyRepo,
+    /// A project's `path` is absolute, escapes the meta root via `..`, or is
+    /// the meta root itself.
+    PathEscapesRoot,
+    /// A `depends_on` entry matches no project name or `provides` capability.
+    UnresolvedDependency,
+}
+
+/// A problem found in a .meta file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    /// The project the problem belongs to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// 1-based line of the offending key, if it could be located.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// 1-based column of the offending key, if it could be located.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {line}, column {column}: ")?,
+            (Some(line), None) => write!(f, "line {line}: ")?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Validate a meta config file and return every problem found.
+///
+/// Fails only if the file can't be read or parsed; semantic problems are
+/// returned as diagnostics, in source order where possible.
+///
+/// Only `meta_path` itself is checked, but `depends_on` entries may refer to
+/// projects pulled in through `include`.
+pub fn validate(meta_path: &Path) -> crate::Result<Vec<Diagnostic>> {
+    let resolved = include::resolve(meta_path)?;
+    let root = &resolved.files[0];
+    Ok(validate_source(
+        &root.source,
+        root.format,
+        &root.config,
+        &resolved.config,
+    ))
+}
+
+/// Validate an already-parsed config against its source text.
+///
+/// `resolved` is the config with includes merged in (and, when parsing,
+/// variables interpolated); it's used to resolve `depends_on` entries and
+/// to check project paths.
+pub(super) fn validate_source(
+    source: &str,
+    format: ConfigFormat,
+    config: &MetaConfig,
+    resolved: &MetaConfig,
+) -> Vec<Diagnostic> {
+    let locate = |key_path: &[&str]| document::locate(source, format, key_path);
+    let diagnostic = |kind, message: String, project: Option<&str>, key_path: &[&str]| {
+        let position = locate(key_path);
+        Diagnostic {
+            kind,
+            message,
+            project: project.map(str::to_string),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+
+    // Unknown keys (serde silently ignores them)
+    let value: Option<Value> = match format {
+        ConfigFormat::Json => serde_json::from_str(source).ok(),
+        ConfigFormat::Yaml => serde_yaml_ng::from_str(source).ok(),
+    };
+    if let Some(Value::Object(root)) = &value {
+        for key in root.keys() {
+            if !TOP_LEVEL_KEYS.contains(&key.as_str()) && !TOP_LEVEL_ALIASES.contains(&key.as_str())
+            {
+                diagnostics.push(diagnostic(
+                    DiagnosticKind::UnknownKey,
+                    unknown_key_message(key, "at the top level", TOP_LEVEL_KEYS),
+                    None,
+                    &[key],
+                ));
+            }
+        }
+        if let Some(Value::Object(defaults)) = root.get("defaults") {
+            for key in defaults.keys() {
+                if !DEFAULTS_KEYS.contains(&key.as_str()) {
+                    diagnostics.push(diagnostic(
+                        DiagnosticKind::UnknownKey,
+                        unknown_key_message(key, "in `defaults`", DEFAULTS_KEYS),
+                        None,
+                        &["defaults", key],
+                    ));
+                }
+            }
+        }
+        if let Some(Value::Object(projects)) = root.get("projects") {
+            for (name, entry) in projects {
+                let Value::Object(fields) = entry else {
+                    continue;
+                };
+                for key in fields.keys() {
+                    if !PROJECT_KEYS.contains(&key.as_str()) {
+                        diagnostics.push(diagnostic(
+                            DiagnosticKind::UnknownKey,
+                            unknown_key_message(key, &format!("in project `{name}`"), PROJECT_KEYS),
+                            Some(name),
+                            &["projects", name, key],
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut names: Vec<&String> = config.projects.keys().collect();
+    names.sort();
+
+    let provided: HashSet<&str> = resolved
+        .projects
+        .values()
+        .filter_map(|entry| match entry {
+            ProjectEntry::Extended { provides, .. } => Some(provides),
+            ProjectEntry::Simple(_) => None,
+        })
+        .flatten()
+        .map(String::as_str)
+        .collect();
+
+    let mut paths = Vec::new();
+    for name in names {
+        let entry = &config.projects[name];
+        let (repo, path, depends_on) = match entry {
+            ProjectEntry::Simple(url) => (Some(url), None, &[][..]),
+            ProjectEntry::Extended {
+                repo,
+                path,
+                depends_on,
+                ..
+            } => (repo.as_ref(), path.as_ref(), &depends_on[..]),
+        };
+
+        if repo.is_some_and(|repo| repo.trim().is_empty()) {
+            let key_path: &[&str] = match entry {
+                ProjectEntry::Simple(_) => &["projects", name],
+                ProjectEntry::Extended { .. } => &["projects", name, "repo"],
+            };
+            diagnostics.push(diagnostic(
+                DiagnosticKind::EmptyRepo,
+                format!("project `{name}` has an empty `repo`"),
+                Some(name),
+                key_path,
+            ));
+        }
+
+        // The effective path, after `${VAR}` interpolation when parsing
+        let path = match resolved.projects.get(name) {
+            Some(ProjectEntry::Extended { path, .. }) => path.as_ref(),
+            Some(ProjectEntry::Simple(_)) => None,
+            None => path,
+        };
+        let raw_path = path.map(String::as_str).unwrap_or(name);
+        match ProjectPath::new(raw_path) {
+            Ok(normalized) => paths.push((name.as_str(), normalized)),
+            Err(e) => diagnostics.push(diagnostic(
+                DiagnosticKind::PathEscapesRoot,
+                format!("project `{name}` has invalid path `{raw_path}`: {e}"),
+                Some(name),
+                &["projects", name, "path"],
+            )),
+        }
+
+        for dependency in depends_on {
+            if !resolved.projects.contains_key(dependency)
+                && !provided.contains(dependency.as_str())
+            {
+                diagnostics.push(diagnostic(
+                    DiagnosticKind::UnresolvedDependency,
+                    format!(
+                        "project `{name}` depends on `{dependency}`, which is not a project name or provided capability"
+                    ),
+                    Some(name),
+                    &["projects", name, "depends_on"],
+                ));
+            }
+        }
+    }
+
+    for conflict in project_path::conflicts(paths) {
+        let (kind, message) = match conflict.kind {
+            PathConflictKind::Duplicate => (
+                DiagnosticKind::DuplicatePath,
+                format!(
+                    "projects `{}` and `{}` both use path `{}`",
+                    conflict.other, conflict.project, conflict.path
+                ),
+            ),
+            PathConflictKind::Nested => (
+                DiagnosticKind::NestedPath,
+                format!(
+                    "project `{}` at `{}` is inside project `{}`",
+                    conflict.project, conflict.path, conflict.other
+                ),
+            ),
+        };
+        diagnostics.push(diagnostic(
+            kind,
+            message,
+            Some(&conflict.project),
+            &["projects", &conflict.project],
+        ));
+    }
+
+    diagnostics.sort_by_key(|d| (d.line.is_none(), d.line, d.column));
+    diagnostics
+}
+
+fn unknown_key_message(key: &str, location: &str, known: &[&str]) -> String {
+    let suggestion = known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min();
+    match suggestion {
+        Some((_, candidate)) => {
+            format!("unknown key `{key}` {location} (did you mean `{candidate}`?)")
+        }
+        None => format!("unknown key `{key}` {location}"),
+    }
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_str(name: &str, content: &str) -> Vec<Diagnostic> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        validate(&path).unwrap()
+    }
+
+    #[test]
+    fn test_validate_clean_config() {
+        let diagnostics = validate_str(
+            ".meta",
+            r#"{"projects": {
+                "api": {"repo": "git@github.com:org/api.git", "provides": ["http-api"]},
+                "web": {"repo": "git@github.com:org/web.git", "depends_on": ["http-api", "api"]}
+            }, "defaults": {"parallel": false}}"#,
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_validate_unknown_keys_with_suggestions() {
+        let diagnostics = validate_str(
+            ".meta.yaml",
+            "projects:\n  api:\n    repo: git@github.com:org/api.git\n    depend_on: [core]\n    tag: [backend]\ndefault:\n  parallel: true\n",
+        );
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "line 4, column 5: unknown key `depend_on` in project `api` (did you mean `depends_on`?)",
+                "line 5, column 5: unknown key `tag` in project `api` (did you mean `tags`?)",
+                "line 6, column 1: unknown key `default` at the top level (did you mean `defaults`?)",
+            ]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.kind == DiagnosticKind::UnknownKey));
+    }
+
+    #[test]
+    fn test_validate_paths_repos_and_dependencies() {
+        let diagnostics = validate_str(
+            ".meta",
+            r#"{"projects": {
+                "a": {"repo": "", "path": "libs/shared"},
+                "b": {"repo": "git@github.com:org/b.git", "path": "libs/./shared/"},
+                "c": {"repo": "git@github.com:org/c.git", "path": "../outside"},
+                "d": {"repo": "git@github.com:org/d.git", "path": "/abs/d", "depends_on": ["missing"]}
+            }}"#,
+        );
+        let kinds: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.kind, d.project.as_deref()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DiagnosticKind::EmptyRepo, Some("a")),
+                (DiagnosticKind::DuplicatePath, Some("b")),
+                (DiagnosticKind::PathEscapesRoot, Some("c")),
+                (DiagnosticKind::PathEscapesRoot, Some("d")),
+                (DiagnosticKind::UnresolvedDependency, Some("d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_parse_fails_on_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".meta");
+        std::fs::write(
+            &path,
+            r#"{"projects": {"api": {"repo": "git@github.com:org/api.git", "tag": ["x"]}}}"#,
+        )
+        .unwrap();
+
+        // Lenient parsing ignores the unknown key
+        let options = crate::config::tests::isolated(dir.path());
+        assert!(crate::config::parse_meta_config_with(&path, &options).is_ok());
+
+        let strict = crate::config::ParseOptions {
+            strict: true,
+            ..options
+        };
+        match crate::config::parse_meta_config_with(&path, &strict) {
+            Err(crate::Error::ConfigInvalid { diagnostics, .. }) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].kind, DiagnosticKind::UnknownKey);
+            }
+            other => panic!("expected ConfigInvalid, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("tag", "tags"), 1);
+        assert_eq!(edit_distance("depend_on", "depends_on"), 1);
+        assert_eq!(edit_distance("repo", "repo"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
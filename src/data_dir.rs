@@ -7,12 +7,15 @@
 //! Use `meta_core::meta_dir()` to get the directory path directly.
 
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Ensure the meta data directory exists, creating it if needed.
 /// Returns the path to the directory.
 pub fn ensure_meta_dir() -> Result<PathBuf> {
-    let dir = crate::meta_dir();
+    ensure_dir(crate::meta_dir())
+}
+
+fn ensure_dir(dir: PathBuf) -> Result<PathBuf> {
     if !dir.exists() {
         std::fs::create_dir_all(&dir).with_context(|| {
             format!("Failed to create meta data directory at {}", dir.display())
@@ -26,7 +29,11 @@ pub fn ensure_meta_dir() -> Result<PathBuf> {
 /// The file may or may not exist. Use `store::read` to read with a default,
 /// or check existence manually.
 pub fn data_file(namespace: &str) -> PathBuf {
-    crate::meta_dir().join(format!("{namespace}.json"))
+    data_file_in(&crate::meta_dir(), namespace)
+}
+
+fn data_file_in(meta_dir: &Path, namespace: &str) -> PathBuf {
+    meta_dir.join(format!("{namespace}.json"))
 }
 
 /// Get the path for a namespaced subdirectory: `~/.meta/<namespace>/`.
@@ -46,18 +53,18 @@ mod tests {
 
     #[test]
     fn test_data_file_path() {
-        std::env::set_var("META_DATA_DIR", "/tmp/test-meta");
-        let path = data_file("worktree");
+        let path = data_file_in(Path::new("/tmp/test-meta"), "worktree");
         assert_eq!(path, PathBuf::from("/tmp/test-meta/worktree.json"));
-        std::env::remove_var("META_DATA_DIR");
+        assert_eq!(
+            data_file("worktree"),
+            crate::meta_dir().join("worktree.json")
+        );
     }
 
     #[test]
     fn test_ensure_meta_dir() {
         let tmp = tempfile::tempdir().unwrap();
-        std::env::set_var("META_DATA_DIR", tmp.path().join("meta-test"));
-        let result = ensure_meta_dir().unwrap();
+        let result = ensure_dir(tmp.path().join("meta-test")).unwrap();
         assert!(result.exists());
-        std::env::remove_var("META_DATA_DIR");
     }
 }
@@ -0,0 +1,243 @@
+//! Typed errors for config, store and lock operations.
+//!
+//! Callers that only need a message can keep using `anyhow` — `Error`
+//! converts into `anyhow::Error` with `?`. Callers that need to react to a
+//! specific failure (e.g. to pick an exit code) can match on the variants.
+
+use crate::config::{ConfigFormat, Diagnostic};
+use std::path::PathBuf;
+
+/// Result type for fallible `meta_core` operations.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by `lock`, `store` and `config` operations.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The lock is held by another process and no retries were allowed.
+    #[error("Lock at {} is held by {}", path.display(), holder(*pid))]
+    LockContended {
+        path: PathBuf,
+        /// PID recorded in the lock file, if it could be read.
+        pid: Option<u32>,
+    },
+
+    /// The lock was still held after all retries were exhausted.
+    #[error(
+        "Failed to acquire lock at {} after {attempts} attempts (held by {})",
+        path.display(),
+        holder(*pid)
+    )]
+    LockTimeout {
+        path: PathBuf,
+        attempts: u32,
+        /// PID recorded in the lock file, if it could be read.
+        pid: Option<u32>,
+    },
+
+    /// A .meta config file is not valid JSON/YAML or doesn't match the schema.
+    #[error(
+        "Failed to parse {format} config file: {}{}: {message}{}",
+        path.display(),
+        location(*line, *column),
+        snippet.as_ref().map(|s| format!("\n{s}")).unwrap_or_default()
+    )]
+    ConfigParse {
+        path: PathBuf,
+        format: ConfigFormat,
+        /// 1-based line of the error, if known.
+        line: Option<usize>,
+        /// 1-based column of the error, if known.
+        column: Option<usize>,
+        message: String,
+        /// The offending source lines with a caret under the error column.
+        snippet: Option<String>,
+    },
+
+    /// A .meta config couldn't be serialized by `write_meta_config`.
+    #[error("Failed to serialize meta config as {format}: {message}")]
+    ConfigSerialize {
+        format: ConfigFormat,
+        message: String,
+    },
+
+    /// A .meta config file parsed, but strict validation found problems.
+    #[error(
+        "Invalid config file {}:{}",
+        path.display(),
+        diagnostics.iter().map(|d| format!("\n  {d}")).collect::<String>()
+    )]
+    ConfigInvalid {
+        path: PathBuf,
+        diagnostics: Vec<Diagnostic>,
+    },
+
+    /// A `${VAR}` reference in a .meta value couldn't be expanded.
+    #[error(
+        "Failed to interpolate `{field}`{} in {}: {message}",
+        project.as_ref().map(|p| format!(" of project `{p}`")).unwrap_or_default(),
+        path.display()
+    )]
+    ConfigInterpolation {
+        /// The .meta file (or included fragment) containing the value.
+        path: PathBuf,
+        project: Option<String>,
+        /// The key holding the value, e.g. `repo`.
+        field: String,
+        message: String,
+    },
+
+    /// A `META_*` environment variable has a value its setting doesn't accept.
+    #[error("Invalid value `{value}` for environment variable {name}: {message}")]
+    InvalidEnv {
+        name: String,
+        value: String,
+        message: String,
+    },
+
+    /// A project's `path` is empty, absolute or leads out of the meta root.
+    #[error(
+        "Invalid path `{value}` for project `{project}` in {}: {source}",
+        path.display()
+    )]
+    InvalidProjectPath {
+        /// The .meta file (or included fragment) defining the project.
+        path: PathBuf,
+        project: String,
+        value: String,
+        source: crate::config::ProjectPathError,
+    },
+
+    /// The selected profile isn't defined in the .meta file.
+    #[error(
+        "Unknown profile `{name}` in {} (available: {})",
+        path.display(),
+        available.join(", ")
+    )]
+    UnknownProfile {
+        name: String,
+        path: PathBuf,
+        /// Profiles the file defines, sorted.
+        available: Vec<String>,
+    },
+
+    /// .meta files include each other in a cycle.
+    #[error(
+        "Config include cycle detected: {}",
+        chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> ")
+    )]
+    IncludeCycle {
+        /// Files along the cycle, with the first repeated at the end.
+        chain: Vec<PathBuf>,
+    },
+
+    /// Projects depend on each other in a cycle.
+    #[error("Dependency cycle detected: {}", cycle.join(" -> "))]
+    DependencyCycle {
+        /// Project names along the cycle, with the first repeated at the end.
+        cycle: Vec<String>,
+    },
+
+    /// A project query expression (e.g. `frontend && !legacy`) is malformed.
+    #[error("Invalid project query `{query}` at position {position}: {message}")]
+    QueryParse {
+        query: String,
+        /// Byte offset of the error in `query`.
+        position: usize,
+        message: String,
+    },
+
+    /// A project identifier (e.g. `platform/api`) is malformed.
+    #[error("Invalid project id `{input}`: {message}")]
+    InvalidProjectId { input: String, message: String },
+
+    /// No project in the meta tree matches the given id, path or name.
+    #[error("No project matches `{input}`")]
+    UnknownProject { input: String },
+
+    /// A bare project name matches projects in several nested meta repos.
+    #[error(
+        "Project name `{input}` is ambiguous; use one of: {}",
+        matches.join(", ")
+    )]
+    AmbiguousProject {
+        input: String,
+        /// Ids of the matching projects, sorted.
+        matches: Vec<String>,
+    },
+
+    /// A directory expected to be a git checkout has no `.git`.
+    #[error("Not a git checkout: {}", path.display())]
+    NotGitRepo { path: PathBuf },
+
+    /// A store file exists but doesn't contain valid JSON for the expected type.
+    #[error("Failed to parse store file: {}", path.display())]
+    StoreCorrupt {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// A versioned store file was written by a newer schema version.
+    #[error(
+        "Store file {} has version {found}, but this version of meta supports up to {supported}; upgrade meta to read it",
+        path.display()
+    )]
+    StoreVersionTooNew {
+        path: PathBuf,
+        found: u32,
+        supported: u32,
+    },
+
+    /// Migrating a versioned store file to the current version failed.
+    #[error(
+        "Failed to migrate store file {} from version {from}: {message}",
+        path.display()
+    )]
+    StoreMigration {
+        path: PathBuf,
+        /// The version the failing (or missing) migration starts from.
+        from: u32,
+        message: String,
+    },
+
+    /// A `store::Transaction` was asked to read or write a store it didn't lock.
+    #[error("Store file {} is not part of the transaction", path.display())]
+    NotInTransaction { path: PathBuf },
+
+    /// Store data could not be serialized.
+    #[error("Failed to serialize store data")]
+    Serialize(#[source] serde_json::Error),
+
+    /// A filesystem operation failed.
+    #[error("I/O error at {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// Build an `Error::Io` for `path`.
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+fn holder(pid: Option<u32>) -> String {
+    match pid {
+        Some(pid) => format!("process {pid}"),
+        None => "another process".to_string(),
+    }
+}
+
+fn location(line: Option<usize>, column: Option<usize>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!(" (line {line}, column {column})"),
+        (Some(line), None) => format!(" (line {line})"),
+        _ => String::new(),
+    }
+}
@@ -3,15 +3,19 @@
 //! Provides:
 //! - `data_dir` — Locate and create the `~/.meta/` data directory and namespaced files
 //! - `lock` — File-based locking with PID staleness detection and retry
-//! - `store` — Atomic JSON read/write with lock-protected updates
+//! - `store` — Atomic JSON read/write with lock-protected updates, versioned schemas and multi-file transactions
+//! - `error` — Typed errors returned by `lock`, `store` and `config`
 
 use std::path::PathBuf;
 
 pub mod config;
 pub mod data_dir;
+pub mod error;
 pub mod lock;
 pub mod store;
 
+pub use error::{Error, Result};
+
 /// Default meta data directory name.
 const META_DIR_NAME: &str = ".meta";
 
@@ -21,12 +25,31 @@ const META_DATA_DIR_ENV: &str = "META_DATA_DIR";
 /// Get the meta data directory path.
 /// Respects `META_DATA_DIR` env var, otherwise defaults to `~/.meta/`.
 pub fn meta_dir() -> PathBuf {
-    if let Ok(override_path) = std::env::var(META_DATA_DIR_ENV) {
-        return PathBuf::from(override_path);
+    meta_dir_from(std::env::var(META_DATA_DIR_ENV).ok())
+}
+
+/// The meta data directory for a `META_DATA_DIR` value.
+fn meta_dir_from(override_path: Option<String>) -> PathBuf {
+    match override_path {
+        Some(override_path) => PathBuf::from(override_path),
+        None => dirs_home().join(META_DIR_NAME),
     }
-    dirs_home().join(META_DIR_NAME)
 }
 
 fn dirs_home() -> PathBuf {
     dirs::home_dir().unwrap_or_else(|| PathBuf::from("/tmp/meta-fallback"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_dir_override() {
+        assert_eq!(
+            meta_dir_from(Some("/tmp/test-meta".to_string())),
+            PathBuf::from("/tmp/test-meta")
+        );
+        assert_eq!(meta_dir_from(None), dirs_home().join(".meta"));
+    }
+}
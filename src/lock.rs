@@ -4,9 +4,9 @@
 //! Writes the current PID into the lock file for stale lock detection.
 //! Provides a RAII guard that releases the lock on drop.
 
-use anyhow::{Context, Result};
+use crate::{Error, Result};
 use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
@@ -34,71 +34,100 @@ impl LockGuard {
 /// Creates the lock file with `O_CREAT | O_EXCL` and writes the current PID.
 /// If the lock file already exists:
 /// 1. Check if the PID inside is still alive (stale lock detection)
-/// 2. If stale, remove and retry
+/// 2. If stale, remove it and try again right away, even with no retries
 /// 3. If alive, wait `retry_ms` milliseconds and retry up to `max_retries` times
 ///
 /// Returns a `LockGuard` that removes the lock file on drop.
+///
+/// Fails with `Error::LockContended` if the lock is held by a live process
+/// (or one whose PID can't be read yet) and `max_retries` is 0, or
+/// `Error::LockTimeout` if it is still held after all retries.
 pub fn acquire(lock_path: &Path, max_retries: u32, retry_ms: u64) -> Result<LockGuard> {
     // Ensure parent directory exists
     if let Some(parent) = lock_path.parent() {
         if !parent.exists() {
-            fs::create_dir_all(parent).with_context(|| {
-                format!("Failed to create lock directory: {}", parent.display())
-            })?;
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
         }
     }
 
     for attempt in 0..=max_retries {
-        match try_create_lock(lock_path) {
-            Ok(guard) => return Ok(guard),
-            Err(_) if attempt < max_retries => {
-                // Lock exists — check if stale
-                if let Some(stale_pid) = stale_pid(lock_path) {
-                    // Double-check: re-read PID to guard against race where
-                    // another process acquired the lock between our checks
-                    if read_lock_pid(lock_path) == Some(stale_pid) {
-                        let _ = fs::remove_file(lock_path);
-                    }
-                    continue;
-                }
-                // Lock is held by a live process — wait and retry
-                thread::sleep(Duration::from_millis(retry_ms));
-            }
-            Err(e) => {
-                return Err(e).with_context(|| {
-                    format!(
-                        "Failed to acquire lock at {} after {} attempts",
-                        lock_path.display(),
-                        max_retries + 1
-                    )
-                });
-            }
+        if let Some(guard) = try_acquire(lock_path)? {
+            return Ok(guard);
+        }
+        if attempt < max_retries {
+            // Lock is held by a live process — wait and retry
+            thread::sleep(Duration::from_millis(retry_ms));
+        }
+    }
+
+    // Only a live holder counts as contention; it may have died since
+    if stale_pid(lock_path).is_some() {
+        if let Some(guard) = try_acquire(lock_path)? {
+            return Ok(guard);
+        }
+    }
+
+    let path = lock_path.to_path_buf();
+    let pid = read_lock_pid(lock_path);
+    if max_retries == 0 {
+        Err(Error::LockContended { path, pid })
+    } else {
+        Err(Error::LockTimeout {
+            path,
+            attempts: max_retries + 1,
+            pid,
+        })
+    }
+}
+
+/// Create the lock file, first removing it if its holder is dead. Returns
+/// `None` if a live process (or one still writing its PID) holds it.
+fn try_acquire(lock_path: &Path) -> Result<Option<LockGuard>> {
+    match try_create_lock(lock_path) {
+        Ok(guard) => return Ok(Some(guard)),
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {
+            return Err(Error::io(lock_path, e));
         }
+        Err(_) => {}
     }
 
-    anyhow::bail!(
-        "Failed to acquire lock at {} after {} retries",
-        lock_path.display(),
-        max_retries
-    )
+    // Lock exists — check if stale
+    let Some(stale_pid) = stale_pid(lock_path) else {
+        return Ok(None);
+    };
+    // Double-check: re-read PID to guard against race where another process
+    // acquired the lock between our checks
+    if read_lock_pid(lock_path) == Some(stale_pid) {
+        match fs::remove_file(lock_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io(lock_path, e)),
+        }
+    }
+    match try_create_lock(lock_path) {
+        Ok(guard) => Ok(Some(guard)),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+        Err(e) => Err(Error::io(lock_path, e)),
+    }
 }
 
 /// Try to create the lock file atomically.
-fn try_create_lock(lock_path: &Path) -> Result<LockGuard> {
+fn try_create_lock(lock_path: &Path) -> io::Result<LockGuard> {
     let mut file = OpenOptions::new()
         .write(true)
         .create_new(true) // O_CREAT | O_EXCL
-        .open(lock_path)
-        .with_context(|| format!("Lock file already exists: {}", lock_path.display()))?;
+        .open(lock_path)?;
+
+    // Create the guard first so the lock file is removed if writing the PID fails
+    let guard = LockGuard {
+        path: lock_path.to_path_buf(),
+    };
 
     // Write current PID
     let pid = std::process::id();
-    writeln!(file, "{pid}")
-        .with_context(|| format!("Failed to write PID to lock file: {}", lock_path.display()))?;
+    writeln!(file, "{pid}")?;
 
-    Ok(LockGuard {
-        path: lock_path.to_path_buf(),
-    })
+    Ok(guard)
 }
 
 /// Check if a lock file is stale (the PID inside is dead).
@@ -129,13 +158,13 @@ fn stale_pid(lock_path: &Path) -> Option<u32> {
 
 /// Check if a process with the given PID is alive.
 #[cfg(unix)]
-fn is_process_alive(pid: u32) -> bool {
+pub(crate) fn is_process_alive(pid: u32) -> bool {
     // kill(pid, 0) checks if process exists without sending a signal
     unsafe { libc::kill(pid as i32, 0) == 0 }
 }
 
 #[cfg(windows)]
-fn is_process_alive(pid: u32) -> bool {
+pub(crate) fn is_process_alive(pid: u32) -> bool {
     use std::ptr::null_mut;
 
     // PROCESS_QUERY_LIMITED_INFORMATION = 0x1000
@@ -163,7 +192,7 @@ fn is_process_alive(pid: u32) -> bool {
 }
 
 #[cfg(not(any(unix, windows)))]
-fn is_process_alive(_pid: u32) -> bool {
+pub(crate) fn is_process_alive(_pid: u32) -> bool {
     // On other platforms, conservatively assume the process is alive
     true
 }
@@ -202,6 +231,26 @@ mod tests {
         // Second acquire should fail (0 retries)
         let result = acquire(&lock_path, 0, 10);
         assert!(result.is_err());
+        match result {
+            Err(Error::LockContended { pid, .. }) => assert_eq!(pid, Some(std::process::id())),
+            _ => panic!("expected LockContended"),
+        }
+    }
+
+    #[test]
+    fn test_acquire_times_out_when_locked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let lock_path = tmp.path().join("test.lock");
+
+        let _guard = acquire(&lock_path, 0, 100).unwrap();
+
+        match acquire(&lock_path, 2, 1) {
+            Err(Error::LockTimeout { attempts, pid, .. }) => {
+                assert_eq!(attempts, 3);
+                assert_eq!(pid, Some(std::process::id()));
+            }
+            _ => panic!("expected LockTimeout"),
+        }
     }
 
     #[test]
@@ -232,4 +281,16 @@ mod tests {
         assert!(lock_path.exists());
         drop(guard);
     }
+
+    #[test]
+    fn test_acquire_without_retries_recovers_stale_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let lock_path = tmp.path().join("stale.lock");
+        fs::write(&lock_path, "999999999\n").unwrap();
+
+        // A dead holder doesn't count as contention
+        let guard = acquire(&lock_path, 0, 10).unwrap();
+        assert_eq!(read_lock_pid(&lock_path), Some(std::process::id()));
+        drop(guard);
+    }
 }
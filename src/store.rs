@@ -1,83 +1,326 @@
 //! Atomic JSON store read/write with lock-protected updates.
 //!
 //! Provides generic utilities for reading, writing, and updating JSON files
-//! with atomic write semantics (write to `.tmp`, then rename) and optional
-//! lock protection for concurrent access.
+//! with atomic write semantics (write to a unique temp file, fsync, then
+//! rename) and optional lock protection for concurrent access.
 
-use anyhow::{Context, Result};
+use crate::{Error, Result};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+mod backup;
+mod transaction;
+mod versioned;
+
+pub use backup::{
+    backup_path, backups, read_with_recovery, RecoveryEvent, RecoveryPolicy, BACKUP_COUNT,
+};
+pub use transaction::{journal_dir, recover_transactions, Transaction};
+pub use versioned::VersionedStore;
+
+/// Age at which a temp file is stale whoever wrote it; a write takes far
+/// less time.
+const TEMP_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Distinguishes temp files of concurrent writes within one process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// How much `write_atomic_bytes_with` does to survive a crash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Rename only. Readers never see a partial file, but after a power
+    /// loss the file may be empty or hold the old content.
+    Atomic,
+    /// Also fsync the temp file before the rename and the directory after,
+    /// so the new content is on disk once the write returns.
+    #[default]
+    Durable,
+}
 
 /// Read a JSON file, returning `T::default()` if the file doesn't exist.
 ///
-/// Returns an error if the file exists but can't be parsed.
+/// A `Transaction` that wrote the file and was interrupted is finished
+/// first. Returns `Error::StoreCorrupt` if the file exists but can't be
+/// parsed; use `read_with_recovery` to fall back to a backup instead.
 pub fn read<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    transaction::recover_store(path, None)?;
+    read_file(path)
+}
+
+/// `read` without transaction recovery, for callers that already did it.
+fn read_file<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
     if !path.exists() {
         return Ok(T::default());
     }
 
-    let content = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read store file: {}", path.display()))?;
+    let content = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
 
     if content.trim().is_empty() {
         return Ok(T::default());
     }
 
-    serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse store file: {}", path.display()))
+    serde_json::from_str(&content).map_err(|source| Error::StoreCorrupt {
+        path: path.to_path_buf(),
+        source,
+    })
 }
 
-/// Write data to a JSON file atomically.
+/// Write data to a JSON file atomically and durably.
 ///
-/// Writes to a temporary file (`.tmp` suffix) then renames to the target path.
-/// This ensures readers never see a partially-written file.
+/// Writes to a unique temporary file next to `path` (`.<name>.<pid>.<n>.tmp`),
+/// fsyncs it, renames it over the target path and fsyncs the directory.
+/// Readers never see a partially-written file, and concurrent writers never
+/// share a temp file.
+///
+/// The previous file is kept as `<name>.bak.1`, and up to `BACKUP_COUNT`
+/// older versions as `<name>.bak.N`, for `read_with_recovery`. A stale
+/// `<name>.tmp` left by older versions is removed.
 pub fn write_atomic<T: Serialize>(path: &Path, data: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(data).map_err(Error::Serialize)?;
+
+    // Best effort, like the temp file cleanup in `write_atomic_bytes_with`
+    let _ = remove_legacy_temp_file(path);
+    backup::rotate(path)?;
+    write_atomic_bytes(path, json.as_bytes())
+}
+
+/// Write raw bytes to a file atomically and durably.
+///
+/// Same semantics as `write_atomic`, for content that is already serialized
+/// (e.g. YAML config files), but without backups.
+pub fn write_atomic_bytes(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_atomic_bytes_with(path, bytes, Durability::Durable)
+}
+
+/// Write raw bytes to a file atomically with the given `Durability`.
+///
+/// The temp file is removed if the write fails. Temp files for `path` left
+/// behind by crashed writers are removed first (see
+/// `remove_stale_temp_files`).
+pub fn write_atomic_bytes_with(path: &Path, bytes: &[u8], durability: Durability) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
     // Ensure parent directory exists
-    if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
-        }
+    if !parent.exists() {
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+
+    // Best effort; a leftover temp file doesn't affect this write
+    let _ = remove_stale_temp_files(path);
+
+    let (tmp_path, mut file) = create_temp_file(path)?;
+    let written = file
+        .write_all(bytes)
+        .and_then(|()| match durability {
+            Durability::Durable => file.sync_all(),
+            Durability::Atomic => Ok(()),
+        })
+        .map_err(|e| Error::io(&tmp_path, e))
+        .and_then(|()| {
+            drop(file);
+            fs::rename(&tmp_path, path).map_err(|e| Error::io(path, e))
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return written;
     }
 
-    let tmp_path = path.with_extension("tmp");
+    if durability == Durability::Durable {
+        sync_dir(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    Ok(())
+}
 
-    let json =
-        serde_json::to_string_pretty(data).with_context(|| "Failed to serialize store data")?;
+/// Temp file name for one write to `path`: `.<name>.<pid>.<n>.tmp`.
+///
+/// The PID lets `remove_stale_temp_files` tell files of crashed writers
+/// from writes in progress.
+fn temp_path(path: &Path, pid: u32, n: u64) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.{pid}.{n}.tmp"))
+}
 
-    std::fs::write(&tmp_path, &json)
-        .with_context(|| format!("Failed to write temp file: {}", tmp_path.display()))?;
+/// Create a temp file for `path` that no other writer uses.
+fn create_temp_file(path: &Path) -> Result<(PathBuf, File)> {
+    let pid = std::process::id();
+    loop {
+        let tmp_path = temp_path(path, pid, TEMP_COUNTER.fetch_add(1, Ordering::Relaxed));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => return Ok((tmp_path, file)),
+            // Left behind by an earlier process with the same PID
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Error::io(&tmp_path, e)),
+        }
+    }
+}
 
-    std::fs::rename(&tmp_path, path)
-        .with_context(|| format!("Failed to rename temp file to: {}", path.display()))?;
+/// Make a rename in `dir` durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
 
+/// Directories can't be opened for fsync here; the rename is still atomic.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Remove temp files for `path` left behind by crashed writers.
+///
+/// Only files named like `write_atomic_bytes_with`'s temp files
+/// (`.<name>.<pid>.<n>.tmp`) are considered. One is stale if the process
+/// that created it is no longer running, or if it is `TEMP_MAX_AGE` old,
+/// since its PID may since have been reused (even by this process). Returns
+/// the number of files removed.
+pub fn remove_stale_temp_files(path: &Path) -> Result<usize> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return Ok(0);
+    };
+    let entries = match fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(Error::io(parent, e)),
+    };
+
+    let prefix = format!(".{name}.");
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::io(parent, e))?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(pid) = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".tmp"))
+            .and_then(|rest| rest.split_once('.'))
+            .and_then(|(pid, n)| n.parse::<u64>().ok().and(pid.parse::<u32>().ok()))
+        else {
+            continue;
+        };
+        if !crate::lock::is_process_alive(pid) || is_older_than(&entry.path(), TEMP_MAX_AGE) {
+            match fs::remove_file(entry.path()) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::io(entry.path(), e)),
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove `<name>.tmp`, the temp file older versions of `write_atomic`
+/// used for `path`, once it is `TEMP_MAX_AGE` old. It doesn't record its
+/// writer, so it may belong to a write in progress until then.
+fn remove_legacy_temp_file(path: &Path) -> Result<()> {
+    let legacy = path.with_extension("tmp");
+    if legacy == path || !is_older_than(&legacy, TEMP_MAX_AGE) {
+        return Ok(());
+    }
+    match fs::remove_file(&legacy) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::io(&legacy, e)),
+    }
+}
+
+/// Whether the file at `path` was last modified at least `age` ago.
+fn is_older_than(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|elapsed| elapsed >= age)
+}
+
 /// Read-modify-write with lock protection.
 ///
 /// 1. Acquires the lock at `lock_path`
-/// 2. Reads the current data from `data_path` (or `T::default()` if missing)
-/// 3. Applies the mutation function `f`
-/// 4. Writes the modified data atomically
-/// 5. Releases the lock (via RAII guard drop)
+/// 2. Finishes interrupted transactions that wrote `data_path` (see
+///    `Transaction`)
+/// 3. Reads the current data from `data_path` (or `T::default()` if missing)
+/// 4. Applies the mutation function `f`
+/// 5. Writes the modified data atomically and durably, even if `f` left it
+///    unchanged
+/// 6. Releases the lock (via RAII guard drop)
 ///
-/// This is the primary API for concurrent store access.
+/// This is the primary API for concurrent store access. Use `try_update`
+/// if the mutation can fail or needs to return a value.
 pub fn update<T, F>(data_path: &Path, lock_path: &Path, f: F) -> Result<()>
 where
     T: DeserializeOwned + Default + Serialize,
     F: FnOnce(&mut T),
 {
     let _guard = crate::lock::acquire(lock_path, 50, 100)?;
+    transaction::recover_store(data_path, Some(lock_path))?;
 
-    let mut data: T = read(data_path)?;
+    let mut data: T = read_file(data_path)?;
     f(&mut data);
     write_atomic(data_path, &data)?;
 
     Ok(())
 }
 
+/// Like `update`, with a mutation that can fail or return a value.
+///
+/// Returns whatever `f` returns. If `f` fails, nothing is written and its
+/// error is returned; store and lock failures are converted into `E`.
+/// Unlike `update`, the file is also left alone if `f` succeeds without
+/// changing the data, so no-op updates cause no I/O or file-watcher events.
+///
+/// ```no_run
+/// # use meta_core::store;
+/// # use std::collections::BTreeMap;
+/// # use std::path::Path;
+/// let id = store::try_update(
+///     Path::new("ids.json"),
+///     Path::new("ids.lock"),
+///     |ids: &mut BTreeMap<String, u64>| {
+///         let next = ids.len() as u64 + 1;
+///         match ids.insert("api".to_string(), next) {
+///             Some(_) => Err(anyhow::anyhow!("`api` already has an id")),
+///             None => Ok(next),
+///         }
+///     },
+/// )?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn try_update<T, R, E, F>(data_path: &Path, lock_path: &Path, f: F) -> Result<R, E>
+where
+    T: DeserializeOwned + Default + Serialize,
+    E: From<Error>,
+    F: FnOnce(&mut T) -> Result<R, E>,
+{
+    let _guard = crate::lock::acquire(lock_path, 50, 100)?;
+    transaction::recover_store(data_path, Some(lock_path))?;
+
+    let mut data: T = read_file(data_path)?;
+    let before = serde_json::to_value(&data).map_err(Error::Serialize)?;
+    let result = f(&mut data)?;
+    let after = serde_json::to_value(&data).map_err(Error::Serialize)?;
+    if after != before {
+        write_atomic(data_path, &data)?;
+    }
+
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,8 +354,84 @@ mod tests {
         let loaded: TestStore = read(&path).unwrap();
         assert_eq!(loaded.items.get("key").unwrap(), "value");
 
-        // Ensure tmp file was cleaned up
-        assert!(!path.with_extension("tmp").exists());
+        // Ensure no temp file was left behind
+        let entries: Vec<_> = std::fs::read_dir(tmp.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_existing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("test.txt");
+
+        write_atomic_bytes_with(&path, b"old", Durability::Atomic).unwrap();
+        write_atomic_bytes(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+
+        // Temp file names are unique per write
+        assert_ne!(temp_path(&path, 1, 0), temp_path(&path, 1, 1));
+        assert_ne!(temp_path(&path, 1, 0), temp_path(&path, 2, 0));
+    }
+
+    #[test]
+    fn test_remove_stale_temp_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+
+        // Above any platform's PID limit, so never a running process
+        let dead_pid = i32::MAX as u32;
+        let dead = temp_path(&path, dead_pid, 7);
+        let ours = temp_path(&path, std::process::id(), 7);
+        let other = temp_path(&tmp.path().join("other.json"), dead_pid, 7);
+        let old_ours = temp_path(&path, std::process::id(), 8);
+        let legacy = path.with_extension("tmp");
+        for file in [&dead, &ours, &old_ours, &other, &legacy] {
+            std::fs::write(file, "partial").unwrap();
+        }
+        // Left by an earlier process with our PID
+        make_old(&old_ours);
+        make_old(&legacy);
+
+        assert_eq!(remove_stale_temp_files(&path).unwrap(), 2);
+        assert!(!dead.exists());
+        assert!(ours.exists());
+        assert!(!old_ours.exists());
+        assert!(other.exists());
+        assert!(legacy.exists());
+
+        assert_eq!(
+            remove_stale_temp_files(&tmp.path().join("missing/store.json")).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_removes_stale_temp_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+        let dead = temp_path(&path, i32::MAX as u32, 3);
+        std::fs::write(&dead, "partial").unwrap();
+
+        let legacy = path.with_extension("tmp");
+        std::fs::write(&legacy, "partial").unwrap();
+
+        // Generic byte writes leave `<name>.tmp` alone, even once it is old
+        write_atomic_bytes(&path, b"{}").unwrap();
+        assert!(!dead.exists());
+        assert!(legacy.exists());
+
+        make_old(&legacy);
+        write_atomic_bytes(&path, b"{}").unwrap();
+        assert!(legacy.exists());
+        write_atomic(&path, &TestStore::default()).unwrap();
+        assert!(!legacy.exists());
+    }
+
+    /// Backdate `path` past `TEMP_MAX_AGE`.
+    fn make_old(path: &Path) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - TEMP_MAX_AGE * 2)
+            .unwrap();
     }
 
     #[test]
@@ -150,6 +469,62 @@ mod tests {
 
         // Lock should be released
         assert!(!lock_path.exists());
+
+        // An unchanged store is still written, creating it if needed
+        let other = tmp.path().join("other.json");
+        update::<TestStore, _>(&other, &lock_path, |_| {}).unwrap();
+        assert!(other.exists());
+        update::<TestStore, _>(&data_path, &lock_path, |_| {}).unwrap();
+        assert!(backup_path(&data_path, 2).exists());
+    }
+
+    #[test]
+    fn test_try_update() {
+        let tmp = tempfile::tempdir().unwrap();
+        let data_path = tmp.path().join("store.json");
+        let lock_path = tmp.path().join("store.lock");
+
+        let len = try_update(&data_path, &lock_path, |store: &mut TestStore| {
+            store.items.insert("a".to_string(), "1".to_string());
+            Ok::<_, Error>(store.items.len())
+        })
+        .unwrap();
+        assert_eq!(len, 1);
+        let written = std::fs::metadata(&data_path).unwrap().modified().unwrap();
+
+        // A failing mutation writes nothing, even if it changed the data
+        let result: anyhow::Result<()> =
+            try_update(&data_path, &lock_path, |store: &mut TestStore| {
+                store.items.clear();
+                anyhow::bail!("invalid")
+            });
+        assert_eq!(result.unwrap_err().to_string(), "invalid");
+
+        // An unchanged store is not rewritten
+        let value = try_update(&data_path, &lock_path, |store: &mut TestStore| {
+            Ok::<_, Error>(store.items["a"].clone())
+        })
+        .unwrap();
+        assert_eq!(value, "1");
+        assert_eq!(
+            std::fs::metadata(&data_path).unwrap().modified().unwrap(),
+            written
+        );
+        assert!(!backup_path(&data_path, 1).exists());
+
+        let store: TestStore = read(&data_path).unwrap();
+        assert_eq!(store.items.len(), 1);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_read_corrupt_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("corrupt.json");
+        std::fs::write(&path, "{not json").unwrap();
+
+        let result: Result<TestStore> = read(&path);
+        assert!(matches!(result, Err(Error::StoreCorrupt { path: p, .. }) if p == path));
     }
 
     #[test]
@@ -0,0 +1,277 @@
+//! Rotating backups of store files and recovery from corrupt ones.
+//!
+//! Before `write_atomic` replaces a store file, the current file becomes
+//! `<name>.bak.1`, and older backups shift up to `<name>.bak.N` (see
+//! `BACKUP_COUNT`). `read_with_recovery` uses them when the store file
+//! itself no longer parses.
+
+use super::{read, read_file, write_atomic_bytes};
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Number of backups `write_atomic` keeps per store file.
+pub const BACKUP_COUNT: usize = 3;
+
+/// What `read_with_recovery` does when the store file is corrupt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Fail with `Error::StoreCorrupt`, like `read`.
+    #[default]
+    Fail,
+    /// Return the newest backup that parses, leaving the corrupt file in
+    /// place until the next write replaces it. Fails with
+    /// `Error::StoreCorrupt` if no backup parses.
+    UseBackup,
+    /// Move the corrupt file aside (`<name>.corrupt-<timestamp>`) and
+    /// return `T::default()`.
+    Quarantine,
+}
+
+/// A recovery performed by `read_with_recovery`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecoveryEvent {
+    /// The data was read from a backup.
+    RestoredFromBackup {
+        path: PathBuf,
+        backup: PathBuf,
+        /// Why the store file couldn't be read.
+        error: String,
+    },
+    /// The corrupt file was moved aside and the default data returned.
+    Quarantined {
+        path: PathBuf,
+        moved_to: PathBuf,
+        /// Why the store file couldn't be read.
+        error: String,
+    },
+}
+
+/// Path of backup `n` (1 is the newest) of `path`: `<name>.bak.<n>`.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{n}"));
+    path.with_file_name(name)
+}
+
+/// Existing backups of `path`, newest first.
+pub fn backups(path: &Path) -> Vec<PathBuf> {
+    (1..=BACKUP_COUNT)
+        .map(|n| backup_path(path, n))
+        .filter(|backup| backup.is_file())
+        .collect()
+}
+
+/// Shift existing backups up by one and make the current file backup 1.
+///
+/// The current file is copied rather than renamed, so `path` exists
+/// throughout and readers never see it missing. (A hard link would share
+/// the file, so an in-place write to `path` would corrupt the backup too.)
+/// The copy is written durably, so a power loss can't truncate it along
+/// with the store. An empty file isn't backed up, so a store zeroed by a
+/// crash doesn't push a good version out of the backups.
+pub(super) fn rotate(path: &Path) -> Result<()> {
+    if !path.is_file() || is_blank(path) {
+        return Ok(());
+    }
+    for n in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, n);
+        match fs::rename(&from, backup_path(path, n + 1)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io(&from, e)),
+        }
+    }
+
+    let content = fs::read(path).map_err(|e| Error::io(path, e))?;
+    write_atomic_bytes(&backup_path(path, 1), &content)
+}
+
+/// Read a JSON store file, recovering from corruption according to `policy`.
+///
+/// Returns the data and, if the file was corrupt and `policy` recovered
+/// from it, the `RecoveryEvent` describing what happened. Callers should
+/// surface the event (e.g. as a warning) rather than drop it.
+///
+/// Unlike `read`, an empty file counts as corrupt if there are backups: a
+/// store that was written before doesn't become empty except by a crash.
+pub fn read_with_recovery<T: DeserializeOwned + Default>(
+    path: &Path,
+    policy: RecoveryPolicy,
+) -> Result<(T, Option<RecoveryEvent>)> {
+    let error = match read(path) {
+        Ok(_) if is_blank(path) && !backups(path).is_empty() => {
+            let source = serde_json::from_str::<serde_json::Value>("").unwrap_err();
+            Error::StoreCorrupt {
+                path: path.to_path_buf(),
+                source,
+            }
+        }
+        Ok(data) => return Ok((data, None)),
+        Err(error @ Error::StoreCorrupt { .. }) => error,
+        Err(error) => return Err(error),
+    };
+
+    match policy {
+        RecoveryPolicy::Fail => Err(error),
+        RecoveryPolicy::UseBackup => {
+            for backup in backups(path) {
+                let Ok(data) = read_file(&backup) else {
+                    continue;
+                };
+                let event = RecoveryEvent::RestoredFromBackup {
+                    path: path.to_path_buf(),
+                    backup,
+                    error: error_chain(&error),
+                };
+                return Ok((data, Some(event)));
+            }
+            Err(error)
+        }
+        RecoveryPolicy::Quarantine => {
+            let moved_to = quarantine_path(path);
+            fs::rename(path, &moved_to).map_err(|e| Error::io(path, e))?;
+            let event = RecoveryEvent::Quarantined {
+                path: path.to_path_buf(),
+                moved_to,
+                error: error_chain(&error),
+            };
+            Ok((T::default(), Some(event)))
+        }
+    }
+}
+
+/// Whether `path` exists and holds nothing but whitespace.
+fn is_blank(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|content| content.trim_ascii().is_empty())
+}
+
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".corrupt-{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    path.with_file_name(name)
+}
+
+/// The error with its sources, e.g. `Failed to parse store file: x: EOF ...`.
+fn error_chain(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::write_atomic;
+    use std::collections::BTreeMap;
+
+    type Store = BTreeMap<String, u32>;
+
+    fn store(n: u32) -> Store {
+        Store::from([("n".to_string(), n)])
+    }
+
+    #[test]
+    fn test_write_atomic_rotates_backups() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+
+        for n in 1..=5 {
+            write_atomic(&path, &store(n)).unwrap();
+        }
+        assert_eq!(read::<Store>(&path).unwrap(), store(5));
+        assert_eq!(
+            backups(&path),
+            vec![
+                tmp.path().join("store.json.bak.1"),
+                tmp.path().join("store.json.bak.2"),
+                tmp.path().join("store.json.bak.3"),
+            ]
+        );
+        assert_eq!(read::<Store>(&backup_path(&path, 1)).unwrap(), store(4));
+        assert_eq!(read::<Store>(&backup_path(&path, 3)).unwrap(), store(2));
+        assert!(!backup_path(&path, 4).exists());
+    }
+
+    #[test]
+    fn test_recovery_policies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+        for n in 1..=3 {
+            write_atomic(&path, &store(n)).unwrap();
+        }
+        // The newest backup is corrupt too
+        fs::write(backup_path(&path, 1), "{").unwrap();
+        fs::write(&path, "{not json").unwrap();
+
+        assert!(matches!(
+            read_with_recovery::<Store>(&path, RecoveryPolicy::Fail),
+            Err(Error::StoreCorrupt { .. })
+        ));
+
+        let (data, event) = read_with_recovery::<Store>(&path, RecoveryPolicy::UseBackup).unwrap();
+        assert_eq!(data, store(1));
+        match event {
+            Some(RecoveryEvent::RestoredFromBackup { backup, error, .. }) => {
+                assert_eq!(backup, backup_path(&path, 2));
+                assert!(error.contains("store.json"), "{error}");
+            }
+            other => panic!("expected RestoredFromBackup, got {other:?}"),
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{not json");
+
+        let (data, event) = read_with_recovery::<Store>(&path, RecoveryPolicy::Quarantine).unwrap();
+        assert_eq!(data, Store::default());
+        match event {
+            Some(RecoveryEvent::Quarantined { moved_to, .. }) => {
+                assert_eq!(fs::read_to_string(moved_to).unwrap(), "{not json");
+            }
+            other => panic!("expected Quarantined, got {other:?}"),
+        }
+        assert!(!path.exists());
+
+        // A readable file needs no recovery
+        write_atomic(&path, &store(4)).unwrap();
+        let (data, event) = read_with_recovery::<Store>(&path, RecoveryPolicy::Quarantine).unwrap();
+        assert_eq!((data, event), (store(4), None));
+    }
+
+    #[test]
+    fn test_empty_file_with_backups_is_corrupt() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+
+        // Without backups an empty file is a new store, as with `read`
+        fs::write(&path, " \n").unwrap();
+        let (data, event) = read_with_recovery::<Store>(&path, RecoveryPolicy::Fail).unwrap();
+        assert_eq!((data, event), (Store::default(), None));
+
+        write_atomic(&path, &store(1)).unwrap();
+        write_atomic(&path, &store(2)).unwrap();
+        // Zeroed by a crash
+        fs::write(&path, "").unwrap();
+        assert_eq!(backups(&path), vec![backup_path(&path, 1)]);
+
+        assert!(matches!(
+            read_with_recovery::<Store>(&path, RecoveryPolicy::Fail),
+            Err(Error::StoreCorrupt { .. })
+        ));
+        let (data, event) = read_with_recovery::<Store>(&path, RecoveryPolicy::UseBackup).unwrap();
+        assert_eq!(data, store(1));
+        assert!(matches!(
+            event,
+            Some(RecoveryEvent::RestoredFromBackup { backup, .. }) if backup == backup_path(&path, 1)
+        ));
+    }
+}
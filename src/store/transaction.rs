@@ -0,0 +1,587 @@
+//! Atomic updates across several store files.
+//!
+//! A `Transaction` locks every store it touches up front, in lock-path
+//! order so two transactions can't deadlock, and stages writes in memory.
+//! `commit` first writes all staged content to a journal, with one copy in
+//! the `journal_dir` of each directory holding a store it writes; once the
+//! last copy is on disk the transaction counts as committed. Then each
+//! store file is replaced and the copies removed.
+//!
+//! If the process dies during `commit`, the next `read`, `update`,
+//! `try_update` or `Transaction::begin` of a store it wrote finishes the
+//! job: a journal with every copy in place is rolled forward by replaying
+//! its writes, and one missing a copy (never committed, or already
+//! replayed) is discarded, as is a partially written copy (only ever a
+//! temp file). `recover_transactions` does the same for a whole journal
+//! directory.
+
+use super::{backup, read_file, remove_stale_temp_files, write_atomic_bytes};
+use crate::lock::LockGuard;
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Directory holding transaction journals, next to the stores they write.
+const JOURNAL_DIR: &str = ".journal";
+
+/// Distinguishes transactions started within one process.
+static TRANSACTION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Directory of the write-ahead journals for the stores in `data_path`'s
+/// directory, e.g. `~/.meta/.journal/` for `~/.meta/worktree.json`.
+pub fn journal_dir(data_path: &Path) -> PathBuf {
+    data_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .join(JOURNAL_DIR)
+}
+
+/// The writes of one committed transaction.
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    writes: Vec<JournalWrite>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalWrite {
+    path: PathBuf,
+    lock: PathBuf,
+    content: String,
+}
+
+impl Journal {
+    /// Paths of the copies of the journal named `name`, one per directory
+    /// it writes to.
+    fn copies(&self, name: &str) -> Vec<PathBuf> {
+        let dirs: BTreeSet<PathBuf> = self.writes.iter().map(|w| journal_dir(&w.path)).collect();
+        dirs.into_iter().map(|dir| dir.join(name)).collect()
+    }
+}
+
+/// Staged writes to several stores, committed together.
+///
+/// ```no_run
+/// # use meta_core::store::Transaction;
+/// # use std::collections::BTreeMap;
+/// # use std::path::Path;
+/// let (worktrees, state) = (Path::new("worktree.json"), Path::new("api/state.json"));
+/// let mut tx = Transaction::begin(&[
+///     (worktrees, Path::new("worktree.lock")),
+///     (state, Path::new("api/state.lock")),
+/// ])?;
+/// let mut list: Vec<String> = tx.read(worktrees)?;
+/// list.push("api".to_string());
+/// tx.write(worktrees, &list)?;
+/// tx.write(state, &BTreeMap::from([("checked_out", true)]))?;
+/// tx.commit()?;
+/// # Ok::<(), meta_core::Error>(())
+/// ```
+///
+/// Dropping a transaction without committing discards its writes. Locks
+/// are held until the transaction is committed or dropped.
+pub struct Transaction {
+    /// Lock path of each store, by data path.
+    stores: BTreeMap<PathBuf, PathBuf>,
+    staged: BTreeMap<PathBuf, String>,
+    _guards: Vec<LockGuard>,
+}
+
+impl Transaction {
+    /// Start a transaction over `stores` (data path, lock path pairs).
+    ///
+    /// Paths are made absolute and their directories canonicalized (see
+    /// `normalize`), so different spellings of a store name the same store
+    /// and journals replay the same way from any working directory.
+    /// Interrupted transactions that wrote any of the stores are recovered
+    /// first. Then all locks are acquired in path order.
+    pub fn begin(stores: &[(&Path, &Path)]) -> Result<Self> {
+        let stores = stores
+            .iter()
+            .map(|(data, lock)| Ok((normalize(data)?, normalize(lock)?)))
+            .collect::<Result<BTreeMap<PathBuf, PathBuf>>>()?;
+        for data_path in stores.keys() {
+            recover_store(data_path, None)?;
+        }
+
+        let guards = lock_all(stores.values())?;
+        Ok(Transaction {
+            stores,
+            staged: BTreeMap::new(),
+            _guards: guards,
+        })
+    }
+
+    /// Read a store, seeing this transaction's staged write if there is one.
+    ///
+    /// Fails with `Error::NotInTransaction` if `data_path` wasn't passed to
+    /// `begin`.
+    pub fn read<T: DeserializeOwned + Default>(&self, data_path: &Path) -> Result<T> {
+        let data_path = self.check(data_path)?;
+        match self.staged.get(&data_path) {
+            Some(content) => serde_json::from_str(content).map_err(|source| Error::StoreCorrupt {
+                path: data_path,
+                source,
+            }),
+            None => read_file(&data_path),
+        }
+    }
+
+    /// Stage a write, replacing any earlier staged write to the same store.
+    ///
+    /// Fails with `Error::NotInTransaction` if `data_path` wasn't passed to
+    /// `begin`.
+    pub fn write<T: Serialize>(&mut self, data_path: &Path, data: &T) -> Result<()> {
+        let data_path = self.check(data_path)?;
+        let content = serde_json::to_string_pretty(data).map_err(Error::Serialize)?;
+        self.staged.insert(data_path, content);
+        Ok(())
+    }
+
+    /// Write all staged changes, or none of them if the process dies first.
+    ///
+    /// Replaced store files are backed up as with `write_atomic`.
+    pub fn commit(self) -> Result<()> {
+        if self.staged.is_empty() {
+            return Ok(());
+        }
+        let journal = Journal {
+            writes: self
+                .staged
+                .iter()
+                .map(|(path, content)| JournalWrite {
+                    path: path.clone(),
+                    lock: self.stores[path].clone(),
+                    content: content.clone(),
+                })
+                .collect(),
+        };
+        let copies = journal.copies(&format!("{}.json", transaction_id()));
+        let bytes = serde_json::to_vec_pretty(&journal).map_err(Error::Serialize)?;
+
+        // The last copy is the commit point: from there on, recovery rolls
+        // forward
+        for (written, copy) in copies.iter().enumerate() {
+            if let Err(e) = write_atomic_bytes(copy, &bytes) {
+                let _ = remove_copies(&copies[..written]);
+                return Err(e);
+            }
+        }
+
+        replay(&journal)?;
+        remove_copies(&copies)
+    }
+
+    /// The normalized `data_path`, if it is one of this transaction's stores.
+    fn check(&self, data_path: &Path) -> Result<PathBuf> {
+        let normalized = normalize(data_path)?;
+        if self.stores.contains_key(&normalized) {
+            Ok(normalized)
+        } else {
+            Err(Error::NotInTransaction {
+                path: data_path.to_path_buf(),
+            })
+        }
+    }
+}
+
+/// `path` made absolute, with its deepest existing directory canonicalized
+/// (resolving `.`, `..` and symlinks).
+fn normalize(path: &Path) -> Result<PathBuf> {
+    let absolute = std::path::absolute(path).map_err(|e| Error::io(path, e))?;
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => Ok(canonical_dir(parent).join(name)),
+        _ => Ok(absolute),
+    }
+}
+
+/// `dir` canonicalized, or its nearest existing ancestor canonicalized with
+/// the missing components appended.
+fn canonical_dir(dir: &Path) -> PathBuf {
+    match fs::canonicalize(dir) {
+        Ok(canonical) => canonical,
+        Err(_) => match (dir.parent(), dir.file_name()) {
+            (Some(parent), Some(name)) => canonical_dir(parent).join(name),
+            _ => dir.to_path_buf(),
+        },
+    }
+}
+
+/// Acquire locks in sorted order, so transactions never wait on each other
+/// in a cycle.
+fn lock_all<'a>(locks: impl IntoIterator<Item = &'a PathBuf>) -> Result<Vec<LockGuard>> {
+    let mut locks: Vec<&PathBuf> = locks.into_iter().collect();
+    locks.sort();
+    locks.dedup();
+    locks
+        .into_iter()
+        .map(|lock| crate::lock::acquire(lock, 50, 100))
+        .collect()
+}
+
+/// Finish or discard every interrupted transaction with a journal in
+/// `journal_dir`.
+///
+/// Returns the number of transactions rolled forward. Fails with
+/// `Error::StoreCorrupt` if a journal can't be parsed; it is left in place
+/// for inspection.
+pub fn recover_transactions(journal_dir: &Path) -> Result<usize> {
+    let mut recovered = 0;
+    for journal_path in pending_journals(journal_dir)? {
+        let journal: Option<Journal> = read_file(&journal_path)?;
+        let Some(journal) = journal else {
+            continue;
+        };
+        if finish(&journal_path, &journal, None)? {
+            recovered += 1;
+        }
+    }
+    Ok(recovered)
+}
+
+/// Finish or discard the interrupted transactions that wrote `data_path`,
+/// before the store is read or written. `held_lock` is the store's lock if
+/// the caller holds it.
+///
+/// Journals that can't be parsed are skipped, so one bad file doesn't
+/// break every store next to it; `recover_transactions` reports them.
+pub(super) fn recover_store(data_path: &Path, held_lock: Option<&Path>) -> Result<()> {
+    let journals = pending_journals(&journal_dir(data_path))?;
+    if journals.is_empty() {
+        return Ok(());
+    }
+    let data_path = normalize(data_path)?;
+    let held_lock = held_lock.map(normalize).transpose()?;
+    for journal_path in journals {
+        let Ok(Some(journal)) = read_file::<Option<Journal>>(&journal_path) else {
+            continue;
+        };
+        if journal.writes.iter().any(|w| w.path == data_path) {
+            finish(&journal_path, &journal, held_lock.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Complete journal copies in `journal_dir`, oldest first. Journal temp
+/// files of dead processes are removed on the way.
+fn pending_journals(journal_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(journal_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(journal_dir, e)),
+    };
+    let mut journals = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io(journal_dir, e))?.path();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(temp) = name.strip_prefix('.') {
+            // `.<id>.json.<pid>.<n>.tmp`: an incomplete copy, rolled back
+            if let Some((journal, _)) = temp.split_once(".json.") {
+                remove_stale_temp_files(&journal_dir.join(format!("{journal}.json")))?;
+            }
+        } else if name.ends_with(".json") {
+            journals.push(path);
+        }
+    }
+    journals.sort();
+    Ok(journals)
+}
+
+/// Under the journal's store locks (except `held_lock`, which the caller
+/// holds), replay it if all its copies are in place, then remove them.
+/// Returns whether it was replayed.
+///
+/// If a concurrent recovery holds the other locks while waiting for
+/// `held_lock`, one side fails with `Error::LockTimeout`.
+fn finish(journal_path: &Path, journal: &Journal, held_lock: Option<&Path>) -> Result<bool> {
+    let _guards = lock_all(
+        journal
+            .writes
+            .iter()
+            .map(|w| &w.lock)
+            .filter(|lock| Some(lock.as_path()) != held_lock),
+    )?;
+    // The committing process may have finished while we waited
+    if !journal_path.exists() {
+        return Ok(false);
+    }
+    let name = journal_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let copies = journal.copies(&name);
+    let committed = copies.iter().all(|copy| copy.exists());
+    if committed {
+        replay(journal)?;
+    }
+    remove_copies(&copies)?;
+    Ok(committed)
+}
+
+/// Write a journal's content to its stores, backing each up first.
+///
+/// Stores that already hold their content (written before a crash) are
+/// left alone, so recovery doesn't push their previous version out of the
+/// backups.
+fn replay(journal: &Journal) -> Result<()> {
+    for write in &journal.writes {
+        if fs::read(&write.path).is_ok_and(|current| current == write.content.as_bytes()) {
+            continue;
+        }
+        backup::rotate(&write.path)?;
+        write_atomic_bytes(&write.path, write.content.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Remove journal copies; ones already gone are fine.
+fn remove_copies(copies: &[PathBuf]) -> Result<()> {
+    for copy in copies {
+        match fs::remove_file(copy) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io(copy, e)),
+        }
+    }
+    Ok(())
+}
+
+/// A name for a transaction's journal that no other transaction uses.
+fn transaction_id() -> String {
+    format!(
+        "{}-{}-{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+        std::process::id(),
+        TRANSACTION_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::read;
+
+    type Store = BTreeMap<String, u32>;
+
+    struct Fixture {
+        _tmp: tempfile::TempDir,
+        a: (PathBuf, PathBuf),
+        b: (PathBuf, PathBuf),
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let tmp = tempfile::tempdir().unwrap();
+            // Journals record canonical paths
+            let dir = tmp.path().canonicalize().unwrap();
+            Fixture {
+                a: (dir.join("worktree.json"), dir.join("worktree.lock")),
+                b: (dir.join("api/state.json"), dir.join("api/state.lock")),
+                _tmp: tmp,
+            }
+        }
+
+        fn begin(&self) -> Result<Transaction> {
+            Transaction::begin(&[(&self.b.0, &self.b.1), (&self.a.0, &self.a.1)])
+        }
+
+        /// A journal writing `content` to each of `stores`.
+        fn journal(&self, stores: &[(&(PathBuf, PathBuf), &str)]) -> Journal {
+            Journal {
+                writes: stores
+                    .iter()
+                    .map(|((path, lock), content)| JournalWrite {
+                        path: path.clone(),
+                        lock: lock.clone(),
+                        content: content.to_string(),
+                    })
+                    .collect(),
+            }
+        }
+
+        /// Write every copy of `journal`, as `commit` does before replaying.
+        fn crash_after_journal(&self, name: &str, journal: &Journal) -> Vec<PathBuf> {
+            let copies = journal.copies(name);
+            for copy in &copies {
+                write_atomic_bytes(copy, &serde_json::to_vec(journal).unwrap()).unwrap();
+            }
+            copies
+        }
+    }
+
+    #[test]
+    fn test_commit_writes_all_stores() {
+        let f = Fixture::new();
+        let mut tx = f.begin().unwrap();
+        assert!(f.a.1.exists() && f.b.1.exists());
+
+        let mut a: Store = tx.read(&f.a.0).unwrap();
+        a.insert("api".to_string(), 1);
+        tx.write(&f.a.0, &a).unwrap();
+        tx.write(&f.b.0, &Store::from([("checked_out".to_string(), 1)]))
+            .unwrap();
+        assert_eq!(tx.read::<Store>(&f.a.0).unwrap(), a);
+        assert!(!f.a.0.exists());
+
+        let other = journal_dir(&f.a.0).join("other.json");
+        assert!(matches!(
+            tx.write(&other, &a),
+            Err(Error::NotInTransaction { path }) if path == other
+        ));
+
+        tx.commit().unwrap();
+        assert_eq!(read::<Store>(&f.a.0).unwrap(), a);
+        assert_eq!(read::<Store>(&f.b.0).unwrap()["checked_out"], 1);
+        assert!(!f.a.1.exists() && !f.b.1.exists());
+        for (path, _) in [&f.a, &f.b] {
+            assert_eq!(fs::read_dir(journal_dir(path)).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_paths_are_normalized() {
+        let f = Fixture::new();
+        let sub = f.a.0.parent().unwrap().join("sub");
+        fs::create_dir(&sub).unwrap();
+        let a_alias = sub.join("../worktree.json");
+        let lock_alias = sub.join("../worktree.lock");
+
+        // Both spellings name one store, so its lock is taken once
+        let mut tx = Transaction::begin(&[(&a_alias, &lock_alias), (&f.a.0, &f.a.1)]).unwrap();
+        tx.write(&a_alias, &Store::from([("x".to_string(), 1)]))
+            .unwrap();
+        assert_eq!(tx.read::<Store>(&f.a.0).unwrap()["x"], 1);
+        assert_eq!(tx.stores.len(), 1);
+        assert!(tx.stores.keys().all(|path| path.is_absolute()));
+        tx.commit().unwrap();
+        assert_eq!(read::<Store>(&f.a.0).unwrap()["x"], 1);
+    }
+
+    #[test]
+    fn test_drop_discards_writes() {
+        let f = Fixture::new();
+        let mut tx = f.begin().unwrap();
+        tx.write(&f.a.0, &Store::from([("x".to_string(), 1)]))
+            .unwrap();
+        drop(tx);
+        assert!(!f.a.0.exists());
+        assert!(!f.a.1.exists());
+    }
+
+    #[test]
+    fn test_recovery_rolls_forward_and_back() {
+        let f = Fixture::new();
+        crate::store::write_atomic(&f.a.0, &Store::from([("old".to_string(), 0)])).unwrap();
+
+        // Crashed after the journal was written: rolled forward
+        let journal = f.journal(&[(&f.a, r#"{"new": 1}"#), (&f.b, r#"{"new": 2}"#)]);
+        let copies = f.crash_after_journal("1.json", &journal);
+        assert_eq!(copies.len(), 2);
+
+        // Crashed while writing the journal: rolled back
+        let partial = journal_dir(&f.a.0).join(format!(".2.json.{}.0.tmp", i32::MAX as u32));
+        fs::write(&partial, r#"{"writes": [{"path": "#).unwrap();
+        let uncommitted = f.journal(&[(&f.a, r#"{"new": 3}"#), (&f.b, r#"{"new": 3}"#)]);
+        let first_copy = &uncommitted.copies("3.json")[0];
+        write_atomic_bytes(first_copy, &serde_json::to_vec(&uncommitted).unwrap()).unwrap();
+
+        let tx = f.begin().unwrap();
+        drop(tx);
+        assert_eq!(read_file::<Store>(&f.a.0).unwrap()["new"], 1);
+        assert_eq!(read_file::<Store>(&f.b.0).unwrap()["new"], 2);
+        assert!(copies.iter().all(|copy| !copy.exists()));
+        assert!(!partial.exists());
+        assert!(!first_copy.exists());
+        assert_eq!(recover_transactions(&journal_dir(&f.a.0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_recover_store_under_its_lock() {
+        let f = Fixture::new();
+        let both = f.journal(&[(&f.a, r#"{"new": 1}"#), (&f.b, r#"{"new": 2}"#)]);
+        let only_b = f.journal(&[(&f.b, r#"{"new": 3}"#)]);
+        let both = f.crash_after_journal("1.json", &both);
+        let only_b = f.crash_after_journal("2.json", &only_b);
+
+        // As `update` does: the store's own lock is held, the others aren't
+        let guard = crate::lock::acquire(&f.a.1, 0, 0).unwrap();
+        recover_store(&f.a.0, Some(&f.a.1)).unwrap();
+        assert_eq!(read_file::<Store>(&f.a.0).unwrap()["new"], 1);
+        assert_eq!(read_file::<Store>(&f.b.0).unwrap()["new"], 2);
+        assert!(both.iter().all(|copy| !copy.exists()));
+        assert!(only_b[0].exists());
+        assert!(f.a.1.exists() && !f.b.1.exists());
+        drop(guard);
+
+        // A later update isn't overwritten by a replay
+        crate::store::update(&f.a.0, &f.a.1, |store: &mut Store| {
+            store.insert("new".to_string(), 9);
+        })
+        .unwrap();
+        assert_eq!(recover_transactions(&journal_dir(&f.b.0)).unwrap(), 1);
+        assert_eq!(read_file::<Store>(&f.a.0).unwrap()["new"], 9);
+        assert_eq!(read_file::<Store>(&f.b.0).unwrap()["new"], 3);
+    }
+
+    #[test]
+    fn test_reads_and_updates_recover_their_store() {
+        let f = Fixture::new();
+        let bad = journal_dir(&f.a.0).join("0.json");
+        write_atomic_bytes(&bad, b"{not json").unwrap();
+        let journal = f.journal(&[(&f.a, r#"{"new": 1}"#), (&f.b, r#"{"new": 2}"#)]);
+        f.crash_after_journal("1.json", &journal);
+
+        // Reading either store rolls the transaction forward
+        assert_eq!(read::<Store>(&f.b.0).unwrap()["new"], 2);
+        assert_eq!(read_file::<Store>(&f.a.0).unwrap()["new"], 1);
+
+        // The unparseable journal doesn't block writes
+        crate::store::update(&f.a.0, &f.a.1, |store: &mut Store| {
+            store.insert("new".to_string(), 4);
+        })
+        .unwrap();
+        assert_eq!(read::<Store>(&f.a.0).unwrap()["new"], 4);
+        assert!(matches!(
+            recover_transactions(&journal_dir(&f.a.0)),
+            Err(Error::StoreCorrupt { path, .. }) if path == bad
+        ));
+    }
+
+    #[test]
+    fn test_recovery_after_partial_commit() {
+        let f = Fixture::new();
+        crate::store::write_atomic(&f.a.0, &Store::from([("old".to_string(), 0)])).unwrap();
+        crate::store::write_atomic(&f.b.0, &Store::from([("old".to_string(), 0)])).unwrap();
+        let journal = f.journal(&[(&f.a, r#"{"new": 1}"#), (&f.b, r#"{"new": 2}"#)]);
+        f.crash_after_journal("1.json", &journal);
+
+        // Crashed after writing the first store, as `commit` does
+        backup::rotate(&f.a.0).unwrap();
+        write_atomic_bytes(&f.a.0, journal.writes[0].content.as_bytes()).unwrap();
+
+        assert_eq!(recover_transactions(&journal_dir(&f.a.0)).unwrap(), 1);
+        assert_eq!(read_file::<Store>(&f.a.0).unwrap()["new"], 1);
+        assert_eq!(read_file::<Store>(&f.b.0).unwrap()["new"], 2);
+        for (path, _) in [&f.a, &f.b] {
+            assert_eq!(
+                backup::backups(path),
+                vec![backup::backup_path(path, 1)],
+                "{}",
+                path.display()
+            );
+            assert_eq!(
+                read_file::<Store>(&backup::backup_path(path, 1)).unwrap()["old"],
+                0
+            );
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Schema-versioned stores with step-by-step migrations.
+//!
+//! A versioned store file wraps its payload with the schema version it was
+//! written with:
+//!
+//! ```json
+//! { "version": 2, "data": { ... } }
+//! ```
+//!
+//! Files written before versioning (a bare payload) are read as version 0.
+//! A bare payload that is itself an object with exactly an integer
+//! `version` and a `data` key can't be told from an envelope and is read
+//! as one; stores with such payloads need a new file name when they adopt
+//! versioning.
+//! When a file is older than the store's current version, the registered
+//! migrations upgrade the payload one version at a time and the result is
+//! written back atomically, under the store's lock.
+
+use super::{read_file, transaction, write_atomic};
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// Upgrades a payload from one version to the next.
+type Migration = Box<dyn Fn(Value) -> std::result::Result<Value, String> + Send + Sync>;
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+/// A JSON store whose file records the schema version of its data.
+///
+/// ```no_run
+/// # use meta_core::store::VersionedStore;
+/// # #[derive(Default, serde::Serialize, serde::Deserialize)]
+/// # struct Worktrees { entries: Vec<String> }
+/// let store = VersionedStore::<Worktrees>::new("worktree.json", "worktree.lock", 2)
+///     // v0 stored a bare list
+///     .migration(0, |list| Ok(serde_json::json!({ "items": list })))
+///     // v1 renamed `items` to `entries`
+///     .migration(1, |mut data| {
+///         let items = data["items"].take();
+///         Ok(serde_json::json!({ "entries": items }))
+///     });
+/// let worktrees = store.read()?;
+/// # Ok::<(), meta_core::Error>(())
+/// ```
+pub struct VersionedStore<T> {
+    data_path: PathBuf,
+    lock_path: PathBuf,
+    version: u32,
+    migrations: BTreeMap<u32, Migration>,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T> VersionedStore<T>
+where
+    T: DeserializeOwned + Default + Serialize,
+{
+    /// A store at `data_path`, locked via `lock_path`, whose current schema
+    /// is `version`.
+    pub fn new(data_path: impl Into<PathBuf>, lock_path: impl Into<PathBuf>, version: u32) -> Self {
+        VersionedStore {
+            data_path: data_path.into(),
+            lock_path: lock_path.into(),
+            version,
+            migrations: BTreeMap::new(),
+            _data: PhantomData,
+        }
+    }
+
+    /// Register the migration from version `from` to `from + 1`.
+    ///
+    /// The function receives the `data` payload as JSON and returns the
+    /// upgraded payload, or a message explaining why it can't.
+    pub fn migration<F>(mut self, from: u32, migrate: F) -> Self
+    where
+        F: Fn(Value) -> std::result::Result<Value, String> + Send + Sync + 'static,
+    {
+        self.migrations.insert(from, Box::new(migrate));
+        self
+    }
+
+    /// The store file.
+    pub fn path(&self) -> &Path {
+        &self.data_path
+    }
+
+    /// The current schema version.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Read the data, returning `T::default()` if the file doesn't exist.
+    ///
+    /// Interrupted transactions that wrote the store are finished first, as
+    /// with `store::read`. An older file is migrated and written back. Fails with
+    /// `Error::StoreVersionTooNew` if the file was written by a newer
+    /// version, `Error::StoreMigration` if a migration is missing or fails,
+    /// or `Error::StoreCorrupt` if the data doesn't parse.
+    pub fn read(&self) -> Result<T> {
+        transaction::recover_store(&self.data_path, None)?;
+        let (data, migrated) = self.load()?;
+        if !migrated {
+            return Ok(data);
+        }
+
+        // Another process may have migrated the file in the meantime
+        let _guard = crate::lock::acquire(&self.lock_path, 50, 100)?;
+        let (data, migrated) = self.load()?;
+        if migrated {
+            self.write(&data)?;
+        }
+        Ok(data)
+    }
+
+    /// Write the data with the current version.
+    pub fn write(&self, data: &T) -> Result<()> {
+        write_atomic(
+            &self.data_path,
+            &Envelope {
+                version: self.version,
+                data,
+            },
+        )
+    }
+
+    /// Read-modify-write under the store's lock, migrating first if needed.
+    ///
+    /// Interrupted transactions that wrote the store are finished first, as
+    /// with `store::update`. As with `store::try_update`, the file is left
+    /// alone if `f` doesn't change the data and no migration ran.
+    pub fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut T),
+    {
+        let _guard = crate::lock::acquire(&self.lock_path, 50, 100)?;
+        transaction::recover_store(&self.data_path, Some(&self.lock_path))?;
+        let (mut data, migrated) = self.load()?;
+        let before = serde_json::to_value(&data).map_err(Error::Serialize)?;
+        f(&mut data);
+        let after = serde_json::to_value(&data).map_err(Error::Serialize)?;
+        if migrated || after != before {
+            self.write(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Read and migrate the data; the flag is set if any migration ran.
+    fn load(&self) -> Result<(T, bool)> {
+        let raw: Option<Value> = read_file(&self.data_path)?;
+        let Some(raw) = raw else {
+            return Ok((T::default(), false));
+        };
+        let (mut version, mut payload) = split_envelope(raw);
+
+        if version > self.version {
+            return Err(Error::StoreVersionTooNew {
+                path: self.data_path.clone(),
+                found: version,
+                supported: self.version,
+            });
+        }
+        let migrated = version < self.version;
+        while version < self.version {
+            let migrate = self
+                .migrations
+                .get(&version)
+                .ok_or_else(|| Error::StoreMigration {
+                    path: self.data_path.clone(),
+                    from: version,
+                    message: format!("no migration registered from version {version}"),
+                })?;
+            payload = migrate(payload).map_err(|message| Error::StoreMigration {
+                path: self.data_path.clone(),
+                from: version,
+                message,
+            })?;
+            version += 1;
+        }
+
+        let data = serde_json::from_value(payload).map_err(|source| Error::StoreCorrupt {
+            path: self.data_path.clone(),
+            source,
+        })?;
+        Ok((data, migrated))
+    }
+}
+
+/// Split a stored value into its version and payload.
+///
+/// Anything other than an object with exactly an integer `version` and a
+/// `data` key is an unversioned payload (version 0).
+fn split_envelope(raw: Value) -> (u32, Value) {
+    match raw {
+        Value::Object(mut map) if map.len() == 2 && map.contains_key("data") => {
+            let version = map
+                .get("version")
+                .and_then(Value::as_u64)
+                .and_then(|v| u32::try_from(v).ok());
+            match version {
+                Some(version) => (version, map.remove("data").unwrap_or_default()),
+                None => (0, Value::Object(map)),
+            }
+        }
+        raw => (0, raw),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Worktrees {
+        entries: Vec<String>,
+    }
+
+    fn store(dir: &Path) -> VersionedStore<Worktrees> {
+        VersionedStore::new(dir.join("worktree.json"), dir.join("worktree.lock"), 2)
+            .migration(0, |list| Ok(json!({ "items": list })))
+            .migration(1, |mut data| {
+                let items = data["items"].take();
+                if !items.is_array() {
+                    return Err("`items` is not a list".to_string());
+                }
+                Ok(json!({ "entries": items }))
+            })
+    }
+
+    #[test]
+    fn test_missing_file_and_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = store(tmp.path());
+        assert_eq!(store.read().unwrap(), Worktrees::default());
+
+        store
+            .update(|data| data.entries.push("api".to_string()))
+            .unwrap();
+        let raw: Value = serde_json::from_slice(&std::fs::read(store.path()).unwrap()).unwrap();
+        assert_eq!(raw, json!({ "version": 2, "data": { "entries": ["api"] } }));
+        assert_eq!(store.read().unwrap().entries, vec!["api"]);
+
+        // No-op updates don't touch the file
+        let written = std::fs::metadata(store.path()).unwrap().modified().unwrap();
+        store.update(|_| {}).unwrap();
+        assert_eq!(
+            std::fs::metadata(store.path()).unwrap().modified().unwrap(),
+            written
+        );
+        assert!(!crate::store::backup_path(store.path(), 2).exists());
+        let empty_dir = tempfile::tempdir().unwrap();
+        let empty = self::store(empty_dir.path());
+        empty.update(|_| {}).unwrap();
+        assert!(!empty.path().exists());
+    }
+
+    #[test]
+    fn test_envelope_shape() {
+        let versioned = |raw: Value| split_envelope(raw).0;
+        assert_eq!(versioned(json!({ "version": 2, "data": [] })), 2);
+        assert_eq!(versioned(json!({ "version": "2", "data": [] })), 0);
+        assert_eq!(versioned(json!({ "data": [], "name": "x" })), 0);
+        assert_eq!(versioned(json!({ "version": 2, "data": [], "x": 1 })), 0);
+        assert_eq!(
+            split_envelope(json!({ "data": [1] })),
+            (0, json!({ "data": [1] }))
+        );
+    }
+
+    #[test]
+    fn test_migrates_unversioned_file_and_writes_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = store(tmp.path());
+        std::fs::write(store.path(), r#"["api", "web"]"#).unwrap();
+
+        assert_eq!(store.read().unwrap().entries, vec!["api", "web"]);
+        let raw: Value = serde_json::from_slice(&std::fs::read(store.path()).unwrap()).unwrap();
+        assert_eq!(raw["version"], 2);
+        assert!(!tmp.path().join("worktree.lock").exists());
+
+        std::fs::write(
+            store.path(),
+            r#"{"version": 1, "data": {"items": ["docs"]}}"#,
+        )
+        .unwrap();
+        store
+            .update(|data| data.entries.push("api".to_string()))
+            .unwrap();
+        assert_eq!(store.read().unwrap().entries, vec!["docs", "api"]);
+    }
+
+    #[test]
+    fn test_version_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = store(tmp.path());
+
+        std::fs::write(store.path(), r#"{"version": 3, "data": {}}"#).unwrap();
+        match store.read() {
+            Err(Error::StoreVersionTooNew {
+                found, supported, ..
+            }) => assert_eq!((found, supported), (3, 2)),
+            other => panic!("expected StoreVersionTooNew, got {other:?}"),
+        }
+
+        std::fs::write(store.path(), r#"{"version": 1, "data": {"items": 5}}"#).unwrap();
+        match store.read() {
+            Err(Error::StoreMigration { from, message, .. }) => {
+                assert_eq!(from, 1);
+                assert!(message.contains("not a list"), "{message}");
+            }
+            other => panic!("expected StoreMigration, got {other:?}"),
+        }
+        // A failed migration leaves the file alone
+        assert!(std::fs::read_to_string(store.path())
+            .unwrap()
+            .contains("\"items\": 5"));
+
+        std::fs::write(store.path(), r#"["api"]"#).unwrap();
+        let no_migrations =
+            VersionedStore::<Worktrees>::new(store.path(), tmp.path().join("worktree.lock"), 1);
+        assert!(matches!(
+            no_migrations.read(),
+            Err(Error::StoreMigration { from: 0, .. })
+        ));
+    }
+}